@@ -1,39 +1,435 @@
-#![feature(never_type)]
-
-use std::{io::{self, BufRead, Error as IoError, Write}};
-use tidc::{json_writer::ToJSON, parser::{artifacts::{with_log_record, with_zap_object}}};
+use std::{fs::File, io::{self, BufRead, BufWriter, Write}, path::{Path, PathBuf}, time::SystemTime};
+use tidc::{csv_writer::CsvWriter, filter::Filter, input, logfmt_writer::LogfmtFormat, json_writer::{Flatten, JsonFormat, Passthrough, ToJSON, TypedValues}, merge::Merge, output::{Origin, WriteRecord}, unified_log_writer::UnifiedLogFormat, projection::{DuplicateKeys, Key, Projection}, parser::{ParseError, artifacts::{LevelFilter, LogLevel, LogRecordRef, LogStr, TimeRef, with_zap_object}, diagnostics::{Diagnostics, SkippedField}, owned::{LogRecord, Time}, reader::{Continuation, LogReader, ReadError}, slow_log::is_slow_log_start, sniff::{LineFormat, Sniffed, is_record_start}, summary::{ErrorSummary, FormatSummary}, time::{TimeBound, TimeRange}}};
 use structopt::StructOpt;
 
-fn run_from_stdin() -> Result<(), tidc::Error> {
-    let stdin = std::io::stdin();
-    let inputs = stdin.lock();
-    let stdout = std::io::stdout();
-    let mut outputs = stdout.lock();
-    
-    for line in inputs.lines() {
-        let line = line?;
-        with_log_record(&line, |r| -> Result<(), IoError> {
-            r.write_json_to(&mut outputs)?;
+/// OnError is the policy of the lines failed to parse.
+enum OnError {
+    Abort,
+    Skip,
+    Passthrough,
+    SideFile(BufWriter<File>),
+}
+
+/// DiagnosticsFormat is how the diagnostics are printed to stderr.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum DiagnosticsFormat {
+    Quiet,
+    Text,
+    /// the text diagnostics with the caret rendering of the error.
+    Verbose,
+    Json,
+}
+
+impl Diagnostics for DiagnosticsFormat {
+    fn on_skipped_field(&mut self, field: SkippedField<'_>) {
+        match self {
+            DiagnosticsFormat::Quiet => {}
+            DiagnosticsFormat::Text => eprintln!("meet error {} during parsing, skipping this field", field.error),
+            DiagnosticsFormat::Verbose => {
+                eprintln!("warning: skipping field `{}`", field.text);
+                eprint!("{}", field.error.render(field.source));
+            }
+            DiagnosticsFormat::Json => {
+                let stderr = io::stderr();
+                let mut stderr = stderr.lock();
+                let _ = field.write_json_to(&mut stderr).and_then(|_| writeln!(stderr));
+            }
+        }
+    }
+}
+
+/// Failures handles the lines failed to parse by the policy, and counts them for the summary.
+struct Failures {
+    policy: OnError,
+    summary: ErrorSummary,
+    /// the records decoded by each format, only counted by the `auto` decoder.
+    formats: FormatSummary,
+    quiet: bool,
+}
+
+impl Failures {
+    fn new(policy: OnError, quiet: bool) -> Self {
+        Self { policy, summary: ErrorSummary::default(), formats: FormatSummary::default(), quiet }
+    }
+
+    fn on_success(&mut self) {
+        self.summary.on_success()
+    }
+
+    fn on_decoded(&mut self, format: LineFormat) {
+        self.formats.on_decoded(format)
+    }
+
+    fn on_failure(&mut self, line: usize, raw: &str, err: ParseError, mut outputs: impl Write) -> Result<(), tidc::Error> {
+        match &mut self.policy {
+            OnError::Abort => {
+                eprint!("{}", err.render(raw));
+                return Err(ReadError::Parse { line, err }.into())
+            }
+            OnError::Skip => {}
+            OnError::Passthrough => {
+                Passthrough { raw, error: &err }.write_json_to(&mut outputs)?;
+                writeln!(outputs)?;
+            }
+            OnError::SideFile(file) => writeln!(file, "{}", raw)?,
+        }
+        self.summary.on_failure(line, &err);
+        Ok(())
+    }
+
+    /// flush the side file and print the summary to stderr if any line failed,
+    /// and the records decoded by each format if they are counted.
+    fn finish(self) -> Result<(), tidc::Error> {
+        if let OnError::SideFile(mut file) = self.policy {
+            file.flush()?;
+        }
+        if self.formats.total() > 0 && !self.quiet {
+            eprint!("{}", self.formats);
+        }
+        if self.summary.failed() > 0 && !self.quiet {
+            eprint!("{}", self.summary);
+        }
+        Ok(())
+    }
+}
+
+/// Context is the state shared by all inputs.
+struct Context {
+    continuation: Continuation,
+    diagnostics: DiagnosticsFormat,
+    /// the format of the fields of zap objects.
+    format: JsonFormat,
+    output: Box<dyn WriteRecord>,
+    failures: Failures,
+    /// whether to write the `_line` attribute.
+    line_numbers: bool,
+    range: TimeRange,
+    /// whether the inputs can be binary searched for `--since`, which needs the `[timestamp]` headers.
+    seekable: bool,
+    /// the lines skipped by seeking the input opened last, which still count in the line numbers.
+    skipped_lines: usize,
+    levels: LevelFilter,
+    filter: Option<Filter>,
+}
+
+impl Context {
+    fn origin<'a>(&self, file: Option<&'a str>, line: usize) -> Origin<'a> {
+        Origin { instance: None, file, line: if self.line_numbers { Some(line) } else { None } }
+    }
+
+    /// whether the record is dropped by `--filter`.
+    fn filtered_out(&self, record: &LogRecordRef<'_>) -> bool {
+        self.filter.as_ref().is_some_and(|filter| !filter.matches(record))
+    }
+
+    /// open the file, seeking to `--since` if it is seekable.
+    fn open(&mut self, path: &Path) -> Result<Box<dyn BufRead>, tidc::Error> {
+        let range = self.range;
+        let (input, skipped_lines) = match range.since {
+            Some(_) if self.seekable => input::open_since(path, |t| range.is_before(t))?,
+            _ => (input::open(path)?, 0),
+        };
+        self.skipped_lines = skipped_lines;
+        Ok(input)
+    }
+}
+
+fn uniformed_log(input: impl BufRead, file: Option<&str>, ctx: &mut Context, mut outputs: impl Write) -> Result<(), tidc::Error> {
+    let mut reader = LogReader::new(input)
+        .with_lines_skipped(ctx.skipped_lines)
+        .with_continuation(ctx.continuation.clone())
+        .with_level_filter(ctx.levels)
+        .with_diagnostics(ctx.diagnostics);
+
+    loop {
+        let (line, err) = match reader.next_numbered_record() {
+            None => break,
+            Some((line, Ok(record))) => {
+                if ctx.range.is_before(&record.time.timestamp) {
+                    continue;
+                }
+                if ctx.range.is_after(&record.time.timestamp) {
+                    break;
+                }
+                if ctx.filtered_out(&record) {
+                    continue;
+                }
+                let origin = ctx.origin(file, line);
+                ctx.output.write_record(&record, origin, &mut outputs)?;
+                ctx.failures.on_success();
+                continue;
+            }
+            Some((_, Err(ReadError::Parse { line, err }))) => (line, err),
+            Some((_, Err(err))) => return Err(err.into()),
+        };
+        ctx.failures.on_failure(line, reader.current_line(), err, &mut outputs)?;
+    }
+    Ok(())
+}
+
+fn zap_object(input: impl BufRead, file: Option<&str>, ctx: &mut Context, mut outputs: impl Write) -> Result<(), tidc::Error> {
+    let mut reader = LogReader::new(input);
+    let mut line_number = 0;
+
+    while let Some(line) = reader.next_line() {
+        line_number += 1;
+        let origin = ctx.origin(file, line_number);
+        let result = with_zap_object(line?, |r| -> io::Result<()> {
+            ctx.format.write_fields(r, origin, &mut outputs)?;
             writeln!(outputs)?;
             Ok(())
-        })??;
+        });
+        match result {
+            Ok(written) => {
+                written?;
+                ctx.failures.on_success();
+            }
+            Err(err) => ctx.failures.on_failure(line_number, reader.current_line(), err.with_line(line_number), &mut outputs)?,
+        }
     }
     Ok(())
 }
 
-fn zap_object_from_stdin() -> Result<(), tidc::Error> {
-    let stdin = std::io::stdin();
-    let inputs = stdin.lock();
-    let stdout = std::io::stdout();
-    let mut outputs = stdout.lock();
+/// decode the inputs whose lines are records, like JSON lines, by `parse`.
+fn record_lines(input: impl BufRead, file: Option<&str>, ctx: &mut Context, mut outputs: impl Write, parse: for<'a> fn(&'a str) -> Result<LogRecordRef<'a>, ParseError>) -> Result<(), tidc::Error> {
+    let mut reader = LogReader::new(input);
+    let mut line_number = 0;
 
-    for line in inputs.lines() {
+    while let Some(line) = reader.next_line() {
+        line_number += 1;
         let line = line?;
-        with_zap_object(&line, |r| -> Result<(), IoError> {
-            r.write_json_to(&mut outputs)?;
-            writeln!(outputs)?;
-            Ok(())
-        })??;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let err = match parse(line) {
+            Ok(record) => {
+                if ctx.range.is_before(&record.time.timestamp) || !ctx.levels.accepts(record.level) {
+                    continue;
+                }
+                if ctx.range.is_after(&record.time.timestamp) {
+                    break;
+                }
+                if ctx.filtered_out(&record) {
+                    continue;
+                }
+                let origin = ctx.origin(file, line_number);
+                ctx.output.write_record(&record, origin, &mut outputs)?;
+                ctx.failures.on_success();
+                continue;
+            }
+            Err(err) => err,
+        };
+        ctx.failures.on_failure(line_number, reader.current_line(), err.with_line(line_number), &mut outputs)?;
+    }
+    Ok(())
+}
+
+/// the record of a line without time, like a plain text line, which takes the time of the record before it,
+/// or the unknown time if there isn't any.
+fn untimed_record<'a>(message: &'a str, time: Option<&'a Time>) -> LogRecordRef<'a> {
+    let time = time.map_or(TimeRef::UNKNOWN, Time::borrowed);
+    LogRecordRef { level: LogLevel::Unknown, time, message: LogStr::Unquoted(message), source: None, entries: Default::default() }
+}
+
+/// write the record decoded by the `auto` decoder if it passes the filters, returns `false` if reading should stop.
+fn write_decoded(record: &LogRecordRef<'_>, format: LineFormat, origin: Origin<'_>, ctx: &mut Context, outputs: &mut dyn Write) -> Result<bool, tidc::Error> {
+    ctx.failures.on_decoded(format);
+    if ctx.range.is_after(&record.time.timestamp) {
+        return Ok(false);
+    }
+    if ctx.range.is_before(&record.time.timestamp) || !ctx.levels.accepts(record.level) || ctx.filtered_out(record) {
+        return Ok(true);
+    }
+    ctx.output.write_record(record, origin, outputs)?;
+    ctx.failures.on_success();
+    Ok(true)
+}
+
+/// decode each record by the format sniffed from its first line. The lines without a `[timestamp]` header
+/// which don't look like any other format are continuation lines of the unified log record before them,
+/// or plain text records otherwise. The lines which fail to parse as JSON or logfmt are plain text too.
+/// Zap objects and plain text take the time of the record before them, and have the unknown time before any record with time.
+fn auto(input: impl BufRead, file: Option<&str>, ctx: &mut Context, mut outputs: impl Write) -> Result<(), tidc::Error> {
+    let mut reader = LogReader::new(input)
+        .with_continuation(ctx.continuation.clone())
+        .with_diagnostics(ctx.diagnostics)
+        .with_record_start(is_record_start);
+    let mut last_time: Option<Time> = None;
+
+    while let Some(line) = reader.next_text() {
+        let line = line?;
+        let origin = ctx.origin(file, line);
+        let sniffed = Sniffed::sniff(reader.current_line());
+        let format = sniffed.format();
+        let keep_reading = match sniffed {
+            Sniffed::UnifiedLog => match reader.parse_current() {
+                Ok(record) => {
+                    last_time = Some(record.time.to_owned());
+                    write_decoded(&record, format, origin, ctx, &mut outputs)?
+                }
+                Err(ReadError::Parse { line, err }) => {
+                    ctx.failures.on_failure(line, reader.current_line(), err, &mut outputs)?;
+                    true
+                }
+                Err(err) => return Err(err.into()),
+            },
+            Sniffed::Json(record) | Sniffed::Logfmt(record) => {
+                last_time = Some(record.time.to_owned());
+                write_decoded(&record, format, origin, ctx, &mut outputs)?
+            }
+            Sniffed::ZapObject(entries) => {
+                let record = LogRecordRef { entries, ..untimed_record("", last_time.as_ref()) };
+                write_decoded(&record, format, origin, ctx, &mut outputs)?
+            }
+            Sniffed::Text => write_decoded(&untimed_record(reader.current_line(), last_time.as_ref()), format, origin, ctx, &mut outputs)?,
+        };
+        if !keep_reading {
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// write the record of a block of the slow query log if it passes the filters, returns `false` if reading should stop.
+fn slow_log_block(block: &str, line: usize, file: Option<&str>, ctx: &mut Context, outputs: &mut dyn Write) -> Result<bool, tidc::Error> {
+    let err = match LogRecordRef::parse_slow_log(block) {
+        Ok(record) => {
+            if ctx.range.is_after(&record.time.timestamp) {
+                return Ok(false);
+            }
+            if ctx.range.is_before(&record.time.timestamp) || !ctx.levels.accepts(record.level) || ctx.filtered_out(&record) {
+                return Ok(true);
+            }
+            ctx.output.write_record(&record, ctx.origin(file, line), outputs)?;
+            ctx.failures.on_success();
+            return Ok(true);
+        }
+        Err(err) => err,
+    };
+    ctx.failures.on_failure(line, block, err.with_line(line), outputs)?;
+    Ok(true)
+}
+
+/// decode the slow query log of TiDB, each block from a `# Time:` line till the next one is a record.
+/// The lines before the first `# Time:` line are skipped, they are the tail of a block in the rotated file before.
+fn slow_log(input: impl BufRead, file: Option<&str>, ctx: &mut Context, mut outputs: impl Write) -> Result<(), tidc::Error> {
+    let mut reader = LogReader::new(input);
+    let mut block = String::new();
+    let mut block_line = 0;
+    let mut line_number = 0;
+    let mut started = false;
+
+    loop {
+        let line = reader.next_line().transpose()?;
+        line_number += 1;
+        started = started || line.is_some_and(is_slow_log_start);
+        if !started && line.is_some() {
+            continue;
+        }
+        if line.is_none_or(is_slow_log_start) && !block.trim().is_empty() {
+            if !slow_log_block(&block, block_line, file, ctx, &mut outputs)? {
+                break;
+            }
+            block.clear();
+        }
+        let line = match line {
+            Some(line) => line,
+            None => break,
+        };
+        if block.trim().is_empty() {
+            block.clear();
+            block_line = line_number;
+        } else {
+            block.push('\n');
+        }
+        block.push_str(line);
+    }
+    Ok(())
+}
+
+/// Failed is a record failed to read, with its raw text.
+struct Failed {
+    raw: String,
+    err: ReadError,
+}
+
+/// Records reads the numbered records of a reader for merging.
+struct Records<R: BufRead>(LogReader<R, DiagnosticsFormat>);
+
+impl<R: BufRead> Iterator for Records<R> {
+    type Item = Result<(usize, LogRecord), Failed>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let err = match self.0.next_numbered_record()? {
+            (line, Ok(record)) => return Some(Ok((line, record.to_owned()))),
+            (_, Err(err)) => err,
+        };
+        Some(Err(Failed { raw: self.0.current_line().to_owned(), err }))
+    }
+}
+
+/// merge the records of the files by their time, each record is tagged with the instance it comes from.
+fn merge(files: &[PathBuf], mut ctx: Context) -> Result<(), tidc::Error> {
+    let instances = input::instance_names(files);
+    let names = files.iter().map(|f| f.to_string_lossy()).collect::<Vec<_>>();
+    let mut sources = Vec::with_capacity(files.len());
+    for path in files {
+        let reader = LogReader::new(ctx.open(path)?)
+            .with_lines_skipped(ctx.skipped_lines)
+            .with_continuation(ctx.continuation.clone())
+            .with_level_filter(ctx.levels)
+            .with_diagnostics(ctx.diagnostics);
+        sources.push(Records(reader));
+    }
+    let stdout = io::stdout();
+    let mut outputs = stdout.lock();
+
+    for (index, item) in Merge::new(sources) {
+        match item {
+            Ok((line, record)) => {
+                if ctx.range.is_before(&record.time.timestamp) {
+                    continue;
+                }
+                if ctx.range.is_after(&record.time.timestamp) {
+                    break;
+                }
+                let record = record.borrowed();
+                if ctx.filtered_out(&record) {
+                    continue;
+                }
+                let origin = Origin { instance: Some(&instances[index]), ..ctx.origin(Some(&names[index]), line) };
+                ctx.output.write_record(&record, origin, &mut outputs)?;
+                ctx.failures.on_success();
+            }
+            Err(Failed { raw, err: ReadError::Parse { line, err } }) => ctx.failures.on_failure(line, &raw, err, &mut outputs)?,
+            Err(Failed { err, .. }) => return Err(err.into()),
+        }
+    }
+    ctx.output.finish(&mut outputs)?;
+    ctx.failures.finish()
+}
+
+/// Decode decodes an input, which is named by the file if it isn't the stdin.
+type Decode = fn(Box<dyn BufRead>, Option<&str>, &mut Context, &mut dyn Write) -> Result<(), tidc::Error>;
+
+/// decode the files, or the stdin if there isn't any file.
+fn run(decode: Decode, files: &[PathBuf], mut ctx: Context) -> Result<(), tidc::Error> {
+    let stdout = io::stdout();
+    let mut outputs = stdout.lock();
+    let result = decode_all(decode, files, &mut ctx, &mut outputs);
+    // the records buffered are still written if the decoding is aborted.
+    ctx.output.finish(&mut outputs)?;
+    result?;
+    ctx.failures.finish()
+}
+
+fn decode_all(decode: Decode, files: &[PathBuf], ctx: &mut Context, outputs: &mut dyn Write) -> Result<(), tidc::Error> {
+    if files.is_empty() {
+        decode(input::decompress(io::stdin().lock())?, None, ctx, outputs)?;
+    }
+    for path in files {
+        let file = path.to_string_lossy();
+        decode(ctx.open(path)?, Some(&file), ctx, outputs)?;
     }
     Ok(())
 }
@@ -49,17 +445,242 @@ fn on_cli_error(e: tidc::Error) -> Result<(), tidc::Error> {
 #[derive(StructOpt, Debug)]
 #[structopt(name = "tidc", about = "A minimal decoder for TiKV uniformed log format.")]
 struct Opt {
-    #[structopt(default_value = "uniformed-log")]
-    decoder: String
+    /// The format of the inputs: `uniformed-log`, `zap-object` (the fields of zap without the header, which are converted
+    /// into records like `json` ones unless written by the `json` output),
+    /// `json` (the JSON lines with the `time`, `level`, `caller` and `msg` keys), `slow-log` (the slow query log of TiDB,
+    /// the `# Key: value` headers are the fields and the SQL is the message) or `auto`, which sniffs
+    /// each line for the unified log, JSON, zap object and logfmt formats, and takes the rest as plain text.
+    /// Defaults to `uniformed-log`. For compatibility, the first argument is taken as the decoder if it names one
+    /// and there isn't such a file, like `tidc zap-object`.
+    #[structopt(short, long)]
+    decoder: Option<String>,
+    /// The files to read, which can be paths, directories or globs like `logs/tikv*.log`.
+    /// Reads the stdin if there isn't any, otherwise each record has the `_file` attribute.
+    /// Files compressed by gzip, zstd or bzip2 are decompressed.
+    inputs: Vec<String>,
+    /// Add the `_line` attribute, the line number where each record starts.
+    #[structopt(short = "n", long)]
+    line_numbers: bool,
+    /// Merge the records of all files into one timeline ordered by time, each record is tagged
+    /// with the `_instance` it comes from. Each file should be sorted by time.
+    #[structopt(long)]
+    merge: bool,
+    /// Only output the records at or after the time, which is a timestamp like `2018/12/15 14:20:11.015 +08:00`
+    /// or `2018-12-15T14:20`, or relative to now like `-15m`. Timestamps without the offset are in the local time
+    /// of records. Uncompressed files are binary searched for the time.
+    #[structopt(long)]
+    since: Option<String>,
+    /// Only output the records before the time, in the same format as `--since`.
+    /// Each input is assumed sorted by time, so reading stops at the first record at or after the time.
+    #[structopt(long)]
+    until: Option<String>,
+    /// Only output the records at the level or more severe: `debug`, `info`, `warn`, `error` or `fatal`.
+    #[structopt(long)]
+    level: Option<String>,
+    /// Only output the records at one of the levels, separated by commas, like `error,fatal`.
+    #[structopt(long, use_delimiter = true)]
+    levels: Vec<String>,
+    /// Only output the records matching the expression, like
+    /// `level >= warn && message ~ "backup" && fields.StoreID in (1, 4)`.
+    /// Paths are `level`, `message`, `time`, `source`, `source.file`, `source.line` and `fields.<key>`;
+    /// operators are `==`, `!=`, `<`, `<=`, `>`, `>=`, `~` (regex), `!~`, `in (..)`, `&&`, `||` and `!`;
+    /// a path alone checks whether it exists.
+    #[structopt(long)]
+    filter: Option<String>,
+    /// Where the lines without a `[timestamp]` header go:
+    /// `field` attaches them as the `_continuation` field, `append` appends them to the last value.
+    #[structopt(long, default_value = "field")]
+    continuation: String,
+    /// What to do with the lines failed to parse: `abort`, `skip`,
+    /// `passthrough` (emit `{"_raw": ..., "_error": ...}` instead) or `side-file` (write them to `--error-file`).
+    #[structopt(long, default_value = "abort")]
+    on_error: String,
+    /// The file to write the lines failed to parse to, for `--on-error side-file`.
+    #[structopt(long, parse(from_os_str))]
+    error_file: Option<PathBuf>,
+    /// Don't print the skipped fields and the summary of lines failed to parse.
+    #[structopt(short, long, conflicts_with = "verbose")]
+    quiet: bool,
+    /// Print the skipped fields with where the error is.
+    #[structopt(short, long)]
+    verbose: bool,
+    /// The format of the diagnostics printed to stderr: `text` or `json`.
+    #[structopt(long, default_value = "text")]
+    diagnostics_format: String,
+    /// How the field values like numbers, booleans, durations (`1m3s`), sizes (`1.2GiB`) and rates (`128MB/s`) are written:
+    /// `off` keeps them as strings, `number` writes them as numbers in seconds, bytes or bytes per second,
+    /// `object` writes durations, sizes and rates as `{"value": .., "unit": .., "raw": ..}`, which is only for the `json` output.
+    /// Defaults to `number` for the `slow-log` decoder, whose fields are mostly numbers, and `off` for the others.
+    #[structopt(long)]
+    typed_values: Option<String>,
+    /// Only write the keys, in the order given, like `time,message,fields.region_id`.
+    /// Keys are `message`, `level`, `source`, `time`, `fields`, `fields.<key>`, `_instance`, `_file` and `_line`.
+    #[structopt(long, use_delimiter = true)]
+    select: Vec<String>,
+    /// Rename the keys in the output, like `fields.region_id=region`.
+    #[structopt(long, use_delimiter = true)]
+    rename: Vec<String>,
+    /// What to do with the fields with the same key in a record: `all` writes all of them with the same key,
+    /// `first` or `last` keeps one of them, `array` collects the values into an array,
+    /// `suffix` writes the second one as `<key>_2` and so on.
+    #[structopt(long, default_value = "all")]
+    duplicate_keys: String,
+    /// The format of the output: `json`, `logfmt`, `csv`, `tsv` or `uniformed-log`, which writes the records
+    /// in the unified log format again, with only the selected fields.
+    /// The columns of `csv` and `tsv` are the `--select`ed keys, or discovered from the first `--discover-columns` records.
+    #[structopt(long, default_value = "json")]
+    output: String,
+    /// The number of records the columns of `csv` and `tsv` are discovered from.
+    #[structopt(long, default_value = "100")]
+    discover_columns: usize,
+    /// Put the fields at the top level of records, with their keys prefixed by `--field-prefix`.
+    /// A field colliding with a built-in key like `message` is written as `fields.<key>`.
+    #[structopt(long)]
+    flatten: bool,
+    /// The prefix of the fields put at the top level by `--flatten`, like `f_`.
+    #[structopt(long, default_value = "fields.")]
+    field_prefix: String,
+    /// Write the `file` and `line` of the source at the top level of records.
+    #[structopt(long)]
+    flatten_source: bool,
+}
+
+/// the decoders, which are also the formats of inputs.
+const DECODERS: &[&str] = &["uniformed-log", "zap-object", "json", "auto", "slow-log"];
+
+/// take the decoder of `--decoder`, or the first argument if it names a decoder and there isn't such a file,
+/// since the decoder used to be the first positional argument.
+fn take_decoder(opt: &mut Opt) -> Result<String, tidc::Error> {
+    let positional = opt.inputs.first().is_some_and(|input| DECODERS.contains(&input.as_str()) && !Path::new(input).exists());
+    match (opt.decoder.take(), positional) {
+        (Some(decoder), true) => Err(tidc::Error::Cli(format!("the decoder is given by both --decoder {} and the argument {}", decoder, opt.inputs[0]))),
+        (Some(decoder), false) => Ok(decoder),
+        (None, true) => Ok(opt.inputs.remove(0)),
+        (None, false) => Ok("uniformed-log".to_owned()),
+    }
 }
 
 fn main() -> Result<(), tidc::Error>{
-    let opt = Opt::from_args();
-    let result = match opt.decoder.as_str() {
-        "uniformed-log" => run_from_stdin(),
-        "zap-object" => zap_object_from_stdin(),
+    let mut opt = Opt::from_args();
+    let decoder = take_decoder(&mut opt)?;
+    let continuation = match opt.continuation.as_str() {
+        "field" => Continuation::default(),
+        "append" => Continuation::AppendToLastValue,
+        other => return Err(tidc::Error::Cli(format!("continuation {} isn't supported", other)))
+    };
+    let on_error = match (opt.on_error.as_str(), &opt.error_file) {
+        ("abort", _) => OnError::Abort,
+        ("skip", _) => OnError::Skip,
+        ("passthrough", _) => OnError::Passthrough,
+        ("side-file", Some(path)) => OnError::SideFile(BufWriter::new(File::create(path)?)),
+        ("side-file", None) => return Err(tidc::Error::Cli("--on-error side-file requires --error-file".to_owned())),
+        (other, _) => return Err(tidc::Error::Cli(format!("on-error policy {} isn't supported", other)))
+    };
+    let diagnostics = match (opt.diagnostics_format.as_str(), opt.quiet, opt.verbose) {
+        (_, true, _) => DiagnosticsFormat::Quiet,
+        ("json", _, _) => DiagnosticsFormat::Json,
+        ("text", _, true) => DiagnosticsFormat::Verbose,
+        ("text", _, false) => DiagnosticsFormat::Text,
+        (other, _, _) => return Err(tidc::Error::Cli(format!("diagnostics format {} isn't supported", other)))
+    };
+    let default_typed_values = if decoder == "slow-log" { "number" } else { "off" };
+    let typed_values = match opt.typed_values.as_deref().unwrap_or(default_typed_values) {
+        "off" => TypedValues::Off,
+        "number" => TypedValues::Number,
+        "object" => TypedValues::Object,
+        other => return Err(tidc::Error::Cli(format!("typed values {} isn't supported", other)))
+    };
+    let key = |s: &str| Key::parse(s).ok_or_else(|| tidc::Error::Cli(format!("key {} isn't supported", s)));
+    let duplicate_keys = match opt.duplicate_keys.as_str() {
+        "all" => DuplicateKeys::All,
+        "first" => DuplicateKeys::First,
+        "last" => DuplicateKeys::Last,
+        "array" => DuplicateKeys::Array,
+        "suffix" => DuplicateKeys::Suffix,
+        other => return Err(tidc::Error::Cli(format!("duplicate keys policy {} isn't supported", other)))
+    };
+    let mut projection = Projection::default().duplicate_keys(duplicate_keys);
+    if !opt.select.is_empty() {
+        projection = projection.select(opt.select.iter().map(|k| key(k)).collect::<Result<_, _>>()?);
+    }
+    for rename in &opt.rename {
+        match rename.split_once('=') {
+            Some((from, to)) if !to.is_empty() => projection = projection.rename(key(from)?, to),
+            _ => return Err(tidc::Error::Cli(format!("rename {} should be like `fields.region_id=region`", rename))),
+        }
+    }
+    let mut format = JsonFormat::default().typed_values(typed_values).projection(projection.clone());
+    if opt.flatten || opt.flatten_source {
+        format = format.flatten(Flatten { fields: Some(opt.field_prefix.clone()).filter(|_| opt.flatten), source: opt.flatten_source });
+    }
+    let output: Box<dyn WriteRecord> = match opt.output.as_str() {
+        "json" => Box::new(format.clone()),
+        "logfmt" | "csv" | "tsv" | "uniformed-log" if opt.flatten || opt.flatten_source => return Err(tidc::Error::Cli(format!("output {} is always flat", opt.output))),
+        "logfmt" | "csv" | "tsv" if typed_values == TypedValues::Object => return Err(tidc::Error::Cli(format!("output {} cannot write typed values as objects", opt.output))),
+        "uniformed-log" => Box::new(UnifiedLogFormat::default().projection(projection)),
+        "logfmt" => Box::new(LogfmtFormat::default().typed_values(typed_values).projection(projection)),
+        "csv" => Box::new(CsvWriter::csv().typed_values(typed_values).projection(projection).discovery(opt.discover_columns)),
+        "tsv" => Box::new(CsvWriter::tsv().typed_values(typed_values).projection(projection).discovery(opt.discover_columns)),
+        other => return Err(tidc::Error::Cli(format!("output {} isn't supported", other)))
+    };
+    // zap objects are written as their fields only by the json output, and converted into records by the others.
+    let zap_fields = decoder == "zap-object" && opt.output == "json";
+    let decode: Decode = match decoder.as_str() {
+        "uniformed-log" => |input, file, ctx, outputs| uniformed_log(input, file, ctx, outputs),
+        "zap-object" if zap_fields => |input, file, ctx, outputs| zap_object(input, file, ctx, outputs),
+        "zap-object" => |input, file, ctx, outputs| record_lines(input, file, ctx, outputs, |s| LogRecordRef::parse_zap_object(s)),
+        "json" => |input, file, ctx, outputs| record_lines(input, file, ctx, outputs, |s| LogRecordRef::parse_json(s)),
+        "auto" => |input, file, ctx, outputs| auto(input, file, ctx, outputs),
+        "slow-log" => |input, file, ctx, outputs| slow_log(input, file, ctx, outputs),
         other => return Err(tidc::Error::Cli(format!("decoder {} isn't supported", other)))
     };
+    let now = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).map(|d| d.as_nanos() as i64).unwrap_or_default();
+    let time_bound = |bound: &Option<String>| match bound {
+        None => Ok(None),
+        Some(s) => TimeBound::parse(s, now).map(Some).ok_or_else(|| tidc::Error::Cli(format!("time {} isn't supported", s))),
+    };
+    let range = TimeRange { since: time_bound(&opt.since)?, until: time_bound(&opt.until)? };
+    if !range.is_unbounded() && zap_fields {
+        return Err(tidc::Error::Cli("decoder zap-object has no time to filter".to_owned()));
+    }
+    let level = |s: &str| match s.to_ascii_uppercase().parse() {
+        Ok(LogLevel::Unknown) | Err(_) => Err(tidc::Error::Cli(format!("level {} isn't supported", s))),
+        Ok(level) => Ok(level),
+    };
+    let mut levels = LevelFilter::all();
+    if let Some(at_least) = &opt.level {
+        levels = levels.and(LevelFilter::at_least(level(at_least)?));
+    }
+    if !opt.levels.is_empty() {
+        levels = levels.and(LevelFilter::only(opt.levels.iter().map(|l| level(l)).collect::<Result<Vec<_>, _>>()?));
+    }
+    if !levels.accepts_all() && zap_fields {
+        return Err(tidc::Error::Cli("decoder zap-object has no level to filter".to_owned()));
+    }
+    let filter = opt.filter.as_deref().map(|f| Filter::parse(f, now)).transpose()?;
+    if filter.is_some() && zap_fields {
+        return Err(tidc::Error::Cli("decoder zap-object cannot be filtered".to_owned()));
+    }
+    let files = input::resolve(&opt.inputs)?;
+    let ctx = Context {
+        continuation,
+        diagnostics,
+        format,
+        output,
+        failures: Failures::new(on_error, opt.quiet),
+        line_numbers: opt.line_numbers,
+        range,
+        seekable: decoder == "uniformed-log",
+        skipped_lines: 0,
+        levels,
+        filter,
+    };
+    let result = match (opt.merge, decoder.as_str()) {
+        (false, _) => run(decode, &files, ctx),
+        (true, "uniformed-log") if !files.is_empty() => merge(&files, ctx),
+        (true, "uniformed-log") => return Err(tidc::Error::Cli("--merge requires the files to merge".to_owned())),
+        (true, other) => return Err(tidc::Error::Cli(format!("decoder {} cannot be merged", other))),
+    };
     match result {
         Err(e) => on_cli_error(e),
         Ok(()) => Ok(())
@@ -0,0 +1,361 @@
+use std::io::{self, Write};
+use crate::{json_writer::TypedValues, output::{Origin, WriteRecord}, parser::{artifacts::*, value::TypedValue}, projection::{FieldValues, Key, Projection}};
+
+pub trait ToCSV {
+    /// write as a cell of a row separated by the delimiter.
+    fn write_csv_to<W: Write>(&self, delimiter: u8, w: W) -> io::Result<()>;
+}
+
+/// write `s` as a cell, which is quoted if it contains the delimiter, quotes or line breaks, as RFC 4180 requires.
+pub fn write_csv_str<W: Write>(mut w: W, s: &str, delimiter: u8) -> io::Result<()> {
+    if !s.bytes().any(|b| b == delimiter || b == b'"' || b == b'\n' || b == b'\r') {
+        return w.write_all(s.as_bytes());
+    }
+    w.write_all(b"\"")?;
+    for (i, part) in s.split('"').enumerate() {
+        if i > 0 {
+            w.write_all(b"\"\"")?;
+        }
+        w.write_all(part.as_bytes())?;
+    }
+    w.write_all(b"\"")
+}
+
+impl ToCSV for &str {
+    fn write_csv_to<W: Write>(&self, delimiter: u8, w: W) -> io::Result<()> {
+        write_csv_str(w, self, delimiter)
+    }
+}
+
+impl <'a> ToCSV for LogStr<'a> {
+    fn write_csv_to<W: Write>(&self, delimiter: u8, w: W) -> io::Result<()> {
+        write_csv_str(w, &self.unescape(), delimiter)
+    }
+}
+
+impl ToCSV for usize {
+    fn write_csv_to<W: Write>(&self, _delimiter: u8, mut w: W) -> io::Result<()> {
+        write!(w, "{}", self)
+    }
+}
+
+impl ToCSV for LogLevel {
+    fn write_csv_to<W: Write>(&self, delimiter: u8, w: W) -> io::Result<()> {
+        self.name().write_csv_to(delimiter, w)
+    }
+}
+
+/// the source is written as `file:line`.
+impl <'a> ToCSV for FileLineRef<'a> {
+    fn write_csv_to<W: Write>(&self, delimiter: u8, w: W) -> io::Result<()> {
+        write_csv_str(w, &format!("{}:{}", self.file, self.line), delimiter)
+    }
+}
+
+impl <'a> ToCSV for TimeRef<'a> {
+    fn write_csv_to<W: Write>(&self, delimiter: u8, w: W) -> io::Result<()> {
+        write_csv_str(w, self.time_str, delimiter)
+    }
+}
+
+impl ToCSV for TypedValue {
+    fn write_csv_to<W: Write>(&self, _delimiter: u8, mut w: W) -> io::Result<()> {
+        match self {
+            TypedValue::Int(n) => write!(w, "{}", n),
+            TypedValue::Bool(b) => write!(w, "{}", b),
+            TypedValue::Float(f)
+            | TypedValue::Duration { seconds: f }
+            | TypedValue::Size { bytes: f }
+            | TypedValue::Rate { bytes_per_second: f } => write!(w, "{}", f),
+        }
+    }
+}
+
+/// the number of records the columns are discovered from by default.
+pub const DEFAULT_DISCOVERY: usize = 100;
+
+/// a cell of a row with the name of its column.
+type Cell = (String, Vec<u8>);
+
+/// CsvWriter writes records as rows of CSV or TSV, after a header of the column names.
+/// The columns are the selected keys, or discovered from the first records if all fields are written.
+/// Missing cells are left empty, and fields out of the discovered columns are dropped.
+pub struct CsvWriter {
+    delimiter: u8,
+    typed_values: TypedValues,
+    projection: Projection,
+    discovery: usize,
+    /// the names of the columns, if known.
+    columns: Option<Vec<String>>,
+    header_written: bool,
+    /// the rows buffered until the columns are discovered.
+    pending: Vec<Vec<Cell>>,
+}
+
+impl CsvWriter {
+    pub fn csv() -> Self {
+        Self::new(b',')
+    }
+
+    pub fn tsv() -> Self {
+        Self::new(b'\t')
+    }
+
+    fn new(delimiter: u8) -> Self {
+        CsvWriter {
+            delimiter,
+            typed_values: TypedValues::default(),
+            projection: Projection::default(),
+            discovery: DEFAULT_DISCOVERY,
+            columns: None,
+            header_written: false,
+            pending: Vec::new(),
+        }
+    }
+
+    /// write durations, sizes and rates as numbers in seconds, bytes and bytes per second if not `Off`.
+    pub fn typed_values(mut self, typed_values: TypedValues) -> Self {
+        self.typed_values = typed_values;
+        self
+    }
+
+    /// the columns are the selected keys, unless all fields are selected.
+    pub fn projection(mut self, projection: Projection) -> Self {
+        self.columns = projection.selected_fields().map(|_| projection.keys().iter().map(|key| match key {
+            Key::Field(field) => field_column(&projection, projection.field_name(field)),
+            key => projection.name(key).to_owned(),
+        }).collect());
+        self.projection = projection;
+        self
+    }
+
+    /// discover the columns from the first records.
+    pub fn discovery(mut self, records: usize) -> Self {
+        self.discovery = records.max(1);
+        self
+    }
+
+    fn cells(&self, record: &LogRecordRef<'_>, origin: Origin<'_>) -> io::Result<Vec<Cell>> {
+        let mut cells = Vec::with_capacity(self.projection.keys().len() + record.entries.len());
+        let mut fields_written = false;
+        for key in self.projection.keys() {
+            let mut cell = Vec::new();
+            match key {
+                Key::Message => record.message.write_csv_to(self.delimiter, &mut cell)?,
+                Key::Level => record.level.write_csv_to(self.delimiter, &mut cell)?,
+                Key::Source => match &record.source {
+                    Some(source) => source.write_csv_to(self.delimiter, &mut cell)?,
+                    None => continue,
+                },
+                Key::Time => record.time.write_csv_to(self.delimiter, &mut cell)?,
+                Key::Fields | Key::Field(_) if fields_written => continue,
+                Key::Fields | Key::Field(_) => {
+                    fields_written = true;
+                    for field in self.projection.fields(&record.entries) {
+                        let mut cell = Vec::new();
+                        self.write_values(&field.values, &mut cell)?;
+                        cells.push((field_column(&self.projection, &field.name), cell));
+                    }
+                    continue;
+                }
+                Key::Instance => match origin.instance {
+                    Some(instance) => instance.write_csv_to(self.delimiter, &mut cell)?,
+                    None => continue,
+                },
+                Key::File => match origin.file {
+                    Some(file) => file.write_csv_to(self.delimiter, &mut cell)?,
+                    None => continue,
+                },
+                Key::Line => match origin.line {
+                    Some(line) => line.write_csv_to(self.delimiter, &mut cell)?,
+                    None => continue,
+                },
+            }
+            cells.push((self.projection.name(key).to_owned(), cell));
+        }
+        Ok(cells)
+    }
+
+    /// the values collected into an array are joined by commas.
+    fn write_values<W: Write>(&self, values: &FieldValues<'_, '_>, mut w: W) -> io::Result<()> {
+        let value = match values {
+            FieldValues::One(value) => value,
+            FieldValues::Many(values) => {
+                let joined = values.iter().map(|value| value.unescape()).collect::<Vec<_>>().join(",");
+                return write_csv_str(w, &joined, self.delimiter);
+            }
+        };
+        if self.typed_values == TypedValues::Off {
+            return value.write_csv_to(self.delimiter, w);
+        }
+        let text = value.unescape();
+        match TypedValue::infer(&text) {
+            None => write_csv_str(w, &text, self.delimiter),
+            Some(typed) => typed.write_csv_to(self.delimiter, &mut w),
+        }
+    }
+
+    /// the columns of the keys in the pending rows, with all fields in the order they are met.
+    fn discover(&self) -> Vec<String> {
+        let mut columns = Vec::<String>::new();
+        let builtins = self.projection.keys().iter()
+            .filter(|key| !matches!(key, Key::Fields | Key::Field(_)))
+            .map(|key| self.projection.name(key))
+            .collect::<Vec<_>>();
+        let mut fields_discovered = false;
+        for key in self.projection.keys() {
+            let name = self.projection.name(key);
+            match key {
+                Key::Fields | Key::Field(_) if fields_discovered => {}
+                Key::Fields | Key::Field(_) => {
+                    fields_discovered = true;
+                    for (name, _) in self.pending.iter().flatten() {
+                        if !builtins.contains(&name.as_str()) && !columns.contains(name) {
+                            columns.push(name.clone());
+                        }
+                    }
+                }
+                _ if self.pending.iter().flatten().any(|(column, _)| column == name) => columns.push(name.to_owned()),
+                _ => {}
+            }
+        }
+        columns
+    }
+
+    fn write_row(&mut self, cells: &[Cell], w: &mut dyn Write) -> io::Result<()> {
+        let columns = self.columns.as_ref().expect("the columns must be known before writing rows");
+        if !self.header_written {
+            self.header_written = true;
+            write_header(columns, self.delimiter, &mut *w)?;
+        }
+        for (i, column) in columns.iter().enumerate() {
+            if i > 0 {
+                w.write_all(&[self.delimiter])?;
+            }
+            if let Some((_, cell)) = cells.iter().find(|(name, _)| name == column) {
+                w.write_all(cell)?;
+            }
+        }
+        writeln!(w)
+    }
+
+    /// discover the columns from the pending rows, then write them.
+    fn flush_pending(&mut self, w: &mut dyn Write) -> io::Result<()> {
+        self.columns = Some(self.discover());
+        for cells in std::mem::take(&mut self.pending) {
+            self.write_row(&cells, w)?;
+        }
+        Ok(())
+    }
+}
+
+/// the column of a field, which is `fields.<name>` if the name collides with a built-in key.
+fn field_column(projection: &Projection, name: &str) -> String {
+    let collides = projection.keys().iter()
+        .any(|key| !matches!(key, Key::Fields | Key::Field(_)) && projection.name(key) == name);
+    if collides { format!("fields.{}", name) } else { name.to_owned() }
+}
+
+fn write_header(columns: &[String], delimiter: u8, w: &mut dyn Write) -> io::Result<()> {
+    for (i, column) in columns.iter().enumerate() {
+        if i > 0 {
+            w.write_all(&[delimiter])?;
+        }
+        write_csv_str(&mut *w, column, delimiter)?;
+    }
+    writeln!(w)
+}
+
+impl WriteRecord for CsvWriter {
+    fn write_record(&mut self, record: &LogRecordRef<'_>, origin: Origin<'_>, w: &mut dyn Write) -> io::Result<()> {
+        let cells = self.cells(record, origin)?;
+        if self.columns.is_some() {
+            return self.write_row(&cells, w);
+        }
+        self.pending.push(cells);
+        if self.pending.len() >= self.discovery {
+            self.flush_pending(w)?;
+        }
+        Ok(())
+    }
+
+    /// write the pending rows, or the header of the selected columns if there isn't any record.
+    fn finish(&mut self, w: &mut dyn Write) -> io::Result<()> {
+        match &self.columns {
+            None if !self.pending.is_empty() => self.flush_pending(w),
+            Some(columns) if !self.header_written => {
+                self.header_written = true;
+                write_header(columns, self.delimiter, w)
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{json_writer::TypedValues, output::{Origin, WriteRecord}, parser::artifacts::with_log_record, projection::{Key, Projection}};
+
+    use super::{CsvWriter, write_csv_str};
+
+    fn write(mut writer: CsvWriter, lines: &[&str]) -> String {
+        let mut buf = Vec::new();
+        for (i, line) in lines.iter().enumerate() {
+            let origin = Origin { line: Some(i + 1), ..Origin::default() };
+            with_log_record(line, |r| writer.write_record(&r, origin, &mut buf).unwrap()).unwrap();
+        }
+        writer.finish(&mut buf).unwrap();
+        String::from_utf8(buf).unwrap()
+    }
+
+    #[test]
+    fn test_csv_str() {
+        fn check(from: &str, delimiter: u8, to: &str) {
+            let mut buf = Vec::new();
+            write_csv_str(&mut buf, from, delimiter).unwrap();
+            assert_eq!(String::from_utf8(buf).unwrap(), to, "failed to encode {:?}", from);
+        }
+
+        check("", b',', "");
+        check("TiKV Started", b',', "TiKV Started");
+        check("a,b", b',', r#""a,b""#);
+        check("a,b", b'\t', "a,b");
+        check("a\tb", b'\t', "\"a\tb\"");
+        check(r#"ID="abc""#, b',', r#""ID=""abc""""#);
+        check("stack\n  at a.rs:1\r\n", b',', "\"stack\n  at a.rs:1\r\n\"");
+    }
+
+    #[test]
+    fn test_csv_writer() {
+        let lines = [
+            r#"[2021/03/09 12:15:13.145 +08:00] [INFO] [a.rs:1] ["start"] [region_id=2] [sql="select 1, 2"]"#,
+            r#"[2021/03/09 12:15:14.145 +08:00] [WARN] [a.rs:2] ["slow \"query\""] [duration=1.5s] [region_id=3]"#,
+            r#"[2021/03/09 12:15:15.145 +08:00] [INFO] [<unknown>] ["late"] [store=1] [message=m]"#,
+        ];
+
+        assert_eq!(write(CsvWriter::csv().discovery(2), &lines), concat!(
+            "message,level,source,time,region_id,sql,duration,_line\n",
+            "start,info,a.rs:1,2021/03/09 12:15:13.145 +08:00,2,\"select 1, 2\",,1\n",
+            "\"slow \"\"query\"\"\",warn,a.rs:2,2021/03/09 12:15:14.145 +08:00,3,,1.5s,2\n",
+            "late,info,,2021/03/09 12:15:15.145 +08:00,,,,3\n",
+        ));
+        assert_eq!(write(CsvWriter::tsv().typed_values(TypedValues::Number), &lines), concat!(
+            "message\tlevel\tsource\ttime\tregion_id\tsql\tduration\tstore\tfields.message\t_line\n",
+            "start\tinfo\ta.rs:1\t2021/03/09 12:15:13.145 +08:00\t2\tselect 1, 2\t\t\t\t1\n",
+            "\"slow \"\"query\"\"\"\twarn\ta.rs:2\t2021/03/09 12:15:14.145 +08:00\t3\t\t1.5\t\t\t2\n",
+            "late\tinfo\t\t2021/03/09 12:15:15.145 +08:00\t\t\t\t1\tm\t3\n",
+        ));
+
+        let projection = Projection::default()
+            .select(vec![Key::Time, Key::Field("region_id".to_owned()), Key::Level])
+            .rename(Key::Field("region_id".to_owned()), "region");
+        assert_eq!(write(CsvWriter::csv().projection(projection.clone()), &lines), concat!(
+            "time,region,level\n",
+            "2021/03/09 12:15:13.145 +08:00,2,info\n",
+            "2021/03/09 12:15:14.145 +08:00,3,warn\n",
+            "2021/03/09 12:15:15.145 +08:00,,info\n",
+        ));
+        assert_eq!(write(CsvWriter::csv().projection(projection), &[]), "time,region,level\n");
+        assert_eq!(write(CsvWriter::csv(), &[]), "");
+    }
+}
@@ -0,0 +1,473 @@
+use std::{borrow::Cow, cmp::Ordering};
+
+use quick_error::quick_error;
+use regex::Regex;
+
+use crate::parser::{artifacts::{LogLevel, LogRecordRef}, time::TimeBound, value::TypedValue};
+
+quick_error! {
+    #[derive(Debug)]
+    pub enum FilterError {
+        Syntax { offset: usize, message: String } {
+            display("{} at byte {} of the filter", message, offset)
+        }
+        Regex(err: regex::Error) {
+            from()
+            source(err)
+            display("invalid regex in the filter: {}", err)
+        }
+    }
+}
+
+/// Filter is a predicate over records, like `level >= warn && message ~ "backup" && fields.StoreID in (1, 4)`.
+///
+/// The paths are `level`, `message`, `time`, `source` (`file:line`), `source.file`, `source.line` and `fields.<key>`.
+/// - `path == value`, `!=`, `<`, `<=`, `>` and `>=` compare the path with the value: levels by severity,
+///   times with a timestamp or a relative time (see `TimeBound`), and other paths numerically if the value
+///   is an unquoted number, duration (`1s`) or size (`128MB`), otherwise as strings.
+/// - `path ~ "regex"` and `path !~ "regex"` match the path against the regex.
+/// - `path in (a, b, ...)` checks whether the path equals any of the values.
+/// - `path` alone checks whether the path exists, i.e. the record has the field.
+///
+/// They can be combined by `&&`, `||`, `!` and parentheses. Comparing a missing field is always false,
+/// and a field appearing many times matches if any of its values matches.
+#[derive(Debug, Clone)]
+pub struct Filter {
+    expr: Expr,
+}
+
+impl Filter {
+    /// parse the filter, relative times are relative to `now_nanos`.
+    pub fn parse(s: &str, now_nanos: i64) -> Result<Self, FilterError> {
+        let mut parser = Parser { tokens: tokenize(s)?, pos: 0, end: s.len(), now_nanos };
+        let expr = parser.parse_or()?;
+        match parser.tokens.get(parser.pos) {
+            None => Ok(Self { expr }),
+            Some((offset, token)) => Err(syntax_error(*offset, format!("unexpected {:?}", token))),
+        }
+    }
+
+    pub fn matches(&self, record: &LogRecordRef<'_>) -> bool {
+        self.expr.eval(record)
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Expr {
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    Exists(Path),
+    Compare(Path, Op, Value),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Path {
+    Level,
+    Message,
+    Time,
+    Source,
+    SourceFile,
+    SourceLine,
+    Field(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Match,
+    NotMatch,
+}
+
+#[derive(Debug, Clone)]
+enum Value {
+    Text(String),
+    /// integers are compared exactly, since TSOs are too large for `f64`.
+    Integer(i128),
+    Number(f64),
+    Level(LogLevel),
+    Time(TimeBound),
+    Regex(Regex),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Word(String),
+    Str(String),
+    Op(Op),
+    And,
+    Or,
+    Not,
+    LParen,
+    RParen,
+    Comma,
+}
+
+fn syntax_error(offset: usize, message: impl Into<String>) -> FilterError {
+    FilterError::Syntax { offset, message: message.into() }
+}
+
+fn tokenize(s: &str) -> Result<Vec<(usize, Token)>, FilterError> {
+    const SYMBOLS: &[(&str, Token)] = &[
+        ("&&", Token::And),
+        ("||", Token::Or),
+        ("==", Token::Op(Op::Eq)),
+        ("!=", Token::Op(Op::Ne)),
+        ("<=", Token::Op(Op::Le)),
+        (">=", Token::Op(Op::Ge)),
+        ("!~", Token::Op(Op::NotMatch)),
+        ("=", Token::Op(Op::Eq)),
+        ("<", Token::Op(Op::Lt)),
+        (">", Token::Op(Op::Gt)),
+        ("~", Token::Op(Op::Match)),
+        ("!", Token::Not),
+        ("(", Token::LParen),
+        (")", Token::RParen),
+        (",", Token::Comma),
+    ];
+    let is_word = |c: char| !c.is_whitespace() && !"()!,=<>~&|\"'".contains(c);
+
+    let mut tokens = Vec::new();
+    let mut pos = 0;
+    while let Some(c) = s[pos..].chars().next() {
+        let rest = &s[pos..];
+        if c.is_whitespace() {
+            pos += c.len_utf8();
+        } else if let Some((symbol, token)) = SYMBOLS.iter().find(|(symbol, _)| rest.starts_with(symbol)) {
+            tokens.push((pos, token.clone()));
+            pos += symbol.len();
+        } else if c == '"' || c == '\'' {
+            let (text, len) = string_literal(rest).ok_or_else(|| syntax_error(pos, "unterminated string"))?;
+            tokens.push((pos, Token::Str(text)));
+            pos += len;
+        } else if is_word(c) {
+            let len = rest.find(|c| !is_word(c)).unwrap_or(rest.len());
+            tokens.push((pos, Token::Word(rest[..len].to_owned())));
+            pos += len;
+        } else {
+            return Err(syntax_error(pos, format!("unexpected {}", c)));
+        }
+    }
+    Ok(tokens)
+}
+
+/// parse the string literal at the start of `s`, returns the text and the length of the literal.
+/// Only the quote and the backslash are escaped, other backslashes are kept for regexes.
+fn string_literal(s: &str) -> Option<(String, usize)> {
+    let quote = s.chars().next()?;
+    let mut text = String::new();
+    let mut chars = s.char_indices().skip(1);
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '\\' => match chars.next()? {
+                (_, c) if c == quote || c == '\\' => text.push(c),
+                (_, c) => {
+                    text.push('\\');
+                    text.push(c);
+                }
+            },
+            c if c == quote => return Some((text, i + 1)),
+            c => text.push(c),
+        }
+    }
+    None
+}
+
+struct Parser {
+    tokens: Vec<(usize, Token)>,
+    pos: usize,
+    /// the length of the filter, for the errors at the end.
+    end: usize,
+    now_nanos: i64,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|(_, token)| token)
+    }
+
+    fn offset(&self) -> usize {
+        self.tokens.get(self.pos).map(|(offset, _)| *offset).unwrap_or(self.end)
+    }
+
+    fn next(&mut self, expected: &str) -> Result<Token, FilterError> {
+        match self.tokens.get(self.pos) {
+            Some((_, token)) => {
+                self.pos += 1;
+                Ok(token.clone())
+            }
+            None => Err(syntax_error(self.end, format!("expecting {}, got the end", expected))),
+        }
+    }
+
+    fn expect(&mut self, expected: Token, name: &str) -> Result<(), FilterError> {
+        let offset = self.offset();
+        match self.next(name)? {
+            token if token == expected => Ok(()),
+            token => Err(syntax_error(offset, format!("expecting {}, got {:?}", name, token))),
+        }
+    }
+
+    fn parse_or(&mut self) -> Result<Expr, FilterError> {
+        let mut expr = self.parse_and()?;
+        while self.peek() == Some(&Token::Or) {
+            self.pos += 1;
+            expr = Expr::Or(Box::new(expr), Box::new(self.parse_and()?));
+        }
+        Ok(expr)
+    }
+
+    fn parse_and(&mut self) -> Result<Expr, FilterError> {
+        let mut expr = self.parse_unary()?;
+        while self.peek() == Some(&Token::And) {
+            self.pos += 1;
+            expr = Expr::And(Box::new(expr), Box::new(self.parse_unary()?));
+        }
+        Ok(expr)
+    }
+
+    fn parse_unary(&mut self) -> Result<Expr, FilterError> {
+        match self.peek() {
+            Some(Token::Not) => {
+                self.pos += 1;
+                Ok(Expr::Not(Box::new(self.parse_unary()?)))
+            }
+            Some(Token::LParen) => {
+                self.pos += 1;
+                let expr = self.parse_or()?;
+                self.expect(Token::RParen, "`)`")?;
+                Ok(expr)
+            }
+            _ => self.parse_predicate(),
+        }
+    }
+
+    fn parse_predicate(&mut self) -> Result<Expr, FilterError> {
+        let offset = self.offset();
+        let path = match self.next("a path")? {
+            Token::Word(word) => parse_path(&word).ok_or_else(|| syntax_error(offset, format!(
+                "unknown path {}, expecting level, message, time, source, source.file, source.line or fields.<key>", word
+            )))?,
+            token => return Err(syntax_error(offset, format!("expecting a path, got {:?}", token))),
+        };
+        match self.peek() {
+            Some(Token::Op(op)) => {
+                let op = *op;
+                self.pos += 1;
+                let value = self.parse_value(&path, op)?;
+                Ok(Expr::Compare(path, op, value))
+            }
+            Some(Token::Word(word)) if word == "in" => {
+                self.pos += 1;
+                self.expect(Token::LParen, "`(`")?;
+                let mut expr = Expr::Compare(path.clone(), Op::Eq, self.parse_value(&path, Op::Eq)?);
+                while self.peek() == Some(&Token::Comma) {
+                    self.pos += 1;
+                    let value = self.parse_value(&path, Op::Eq)?;
+                    expr = Expr::Or(Box::new(expr), Box::new(Expr::Compare(path.clone(), Op::Eq, value)));
+                }
+                self.expect(Token::RParen, "`)`")?;
+                Ok(expr)
+            }
+            _ => Ok(Expr::Exists(path)),
+        }
+    }
+
+    fn parse_value(&mut self, path: &Path, op: Op) -> Result<Value, FilterError> {
+        let offset = self.offset();
+        let (text, quoted) = match self.next("a value")? {
+            Token::Word(word) => (word, false),
+            Token::Str(s) => (s, true),
+            token => return Err(syntax_error(offset, format!("expecting a value, got {:?}", token))),
+        };
+        let value = match (path, op) {
+            (_, Op::Match) | (_, Op::NotMatch) => Value::Regex(Regex::new(&text)?),
+            (Path::Level, _) => match text.to_ascii_uppercase().parse() {
+                Ok(LogLevel::Unknown) if !text.eq_ignore_ascii_case("unknown") && text != LogLevel::Unknown.name() => {
+                    return Err(syntax_error(offset, format!("unknown level {}", text)))
+                }
+                Ok(level) => Value::Level(level),
+                Err(_) => return Err(syntax_error(offset, format!("unknown level {}", text))),
+            },
+            (Path::Time, _) => match TimeBound::parse(&text, self.now_nanos) {
+                Some(bound) => Value::Time(bound),
+                None => return Err(syntax_error(offset, format!("invalid time {}", text))),
+            },
+            _ if !quoted => match (parse_integer(&text), number(&text)) {
+                (Some(n), _) => Value::Integer(n),
+                (None, Some(n)) => Value::Number(n),
+                (None, None) => Value::Text(text),
+            },
+            _ => Value::Text(text),
+        };
+        Ok(value)
+    }
+}
+
+fn parse_path(word: &str) -> Option<Path> {
+    let path = match word {
+        "level" => Path::Level,
+        "message" | "msg" => Path::Message,
+        "time" => Path::Time,
+        "source" => Path::Source,
+        "source.file" => Path::SourceFile,
+        "source.line" => Path::SourceLine,
+        _ => match word.strip_prefix("fields.") {
+            Some(key) if !key.is_empty() => Path::Field(key.to_owned()),
+            _ => return None,
+        },
+    };
+    Some(path)
+}
+
+impl Op {
+    fn test(self, ordering: Ordering) -> bool {
+        match self {
+            Op::Eq => ordering == Ordering::Equal,
+            Op::Ne => ordering != Ordering::Equal,
+            Op::Lt => ordering == Ordering::Less,
+            Op::Le => ordering != Ordering::Greater,
+            Op::Gt => ordering == Ordering::Greater,
+            Op::Ge => ordering != Ordering::Less,
+            Op::Match | Op::NotMatch => false,
+        }
+    }
+}
+
+impl Path {
+    /// the texts of the path in the record, fields may appear many times.
+    fn texts<'a>(&self, record: &'a LogRecordRef<'_>) -> Vec<Cow<'a, str>> {
+        match self {
+            Path::Level => vec![Cow::Borrowed(record.level.name())],
+            Path::Message => vec![record.message.unescape()],
+            Path::Time => vec![Cow::Borrowed(record.time.time_str)],
+            Path::Source => record.source.iter().map(|s| Cow::Owned(format!("{}:{}", s.file, s.line))).collect(),
+            Path::SourceFile => record.source.iter().map(|s| Cow::Borrowed(&*s.file)).collect(),
+            Path::SourceLine => record.source.iter().map(|s| Cow::Borrowed(&*s.line)).collect(),
+            Path::Field(key) => record.entries
+                .iter()
+                .filter(|field| field.key.unescape() == key.as_str())
+                .map(|field| field.value.unescape())
+                .collect(),
+        }
+    }
+}
+
+/// parse an integer like `42` or `-42`, including the ones too large for `f64` like TSOs.
+fn parse_integer(s: &str) -> Option<i128> {
+    let digits = s.strip_prefix(['-', '+']).unwrap_or(s);
+    if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    s.parse().ok()
+}
+
+/// the text as a number, durations, sizes and rates are normalized.
+fn number(s: &str) -> Option<f64> {
+    parse_integer(s).map(|n| n as f64).or_else(|| TypedValue::infer(s)?.as_f64())
+}
+
+impl Expr {
+    fn eval(&self, record: &LogRecordRef<'_>) -> bool {
+        match self {
+            Expr::And(lhs, rhs) => lhs.eval(record) && rhs.eval(record),
+            Expr::Or(lhs, rhs) => lhs.eval(record) || rhs.eval(record),
+            Expr::Not(expr) => !expr.eval(record),
+            Expr::Exists(path) => !path.texts(record).is_empty(),
+            Expr::Compare(path, op, value) => match value {
+                Value::Level(level) => op.test(record.level.cmp(level)),
+                Value::Time(bound) => op.test(bound.cmp_timestamp(&record.time.timestamp)),
+                Value::Regex(regex) => {
+                    let texts = path.texts(record);
+                    match op {
+                        Op::NotMatch => !texts.is_empty() && !texts.iter().any(|t| regex.is_match(t)),
+                        _ => texts.iter().any(|t| regex.is_match(t)),
+                    }
+                }
+                Value::Integer(n) => path.texts(record).iter().any(|t| match parse_integer(t) {
+                    Some(i) => op.test(i.cmp(n)),
+                    None => number(t).and_then(|v| v.partial_cmp(&(*n as f64))).is_some_and(|ordering| op.test(ordering)),
+                }),
+                Value::Number(n) => path.texts(record).iter().any(|t| {
+                    number(t).and_then(|v| v.partial_cmp(n)).is_some_and(|ordering| op.test(ordering))
+                }),
+                Value::Text(s) => path.texts(record).iter().any(|t| op.test(t.as_ref().cmp(s.as_str()))),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parser::artifacts::LogRecordRef;
+
+    use super::{Filter, FilterError};
+
+    const LINE: &str = r#"[2021/03/09 12:15:13.145 +08:00] [WARN] [backup.rs:254] ["backup range finished"] [StoreID=4] [take=992.547µs] [speed=128MB/s] [region="{id: 1}"] [path="C:\\tikv"] [StoreID=7]"#;
+
+    fn matches(filter: &str) -> bool {
+        let record = LogRecordRef::parse(LINE).unwrap();
+        Filter::parse(filter, 0).unwrap_or_else(|e| panic!("failed to parse {}: {}", filter, e)).matches(&record)
+    }
+
+    #[test]
+    fn test_filter() {
+        assert!(matches(r#"level >= warn && message ~ "backup" && fields.StoreID in (1,4)"#));
+        assert!(!matches(r#"level >= error || message !~ "^backup""#));
+        assert!(matches("level == WARN && level < error && level in (warn, fatal)"));
+        assert!(matches(r#"time >= "2021/03/09 12:15" && time < "2021-03-09T04:16:00Z""#));
+        assert!(matches("time > now") && !matches("time < -1h"), "now is the epoch in the tests");
+
+        assert!(matches("fields.StoreID && !fields.RegionID"));
+        assert!(matches("fields.StoreID > 5 && fields.StoreID < 5"), "any of the duplicated fields may match");
+        assert!(matches("fields.take < 1ms && fields.take > 900us && fields.speed >= 128MB"));
+        assert!(!matches("fields.RegionID != 1"), "comparing a missing field is false");
+        assert!(!matches("fields.region > 1"), "comparing a string numerically is false");
+        assert!(matches(r#"fields.region == "{id: 1}" && fields.path == "C:\\tikv""#));
+        assert!(matches(r#"fields.path ~ 'C:\\\\tikv$' && fields.path ~ "^\w:""#));
+        assert!(matches(r#"source == "backup.rs:254" && source.file ~ "\.rs$" && source.line == 254"#));
+        assert!(matches("!(level == info || msg ~ panic) && (fields.StoreID == 4)"));
+        let record = LogRecordRef::parse("[2021/03/09 12:15:13.145 +08:00] [TRACE] [a.rs:1] [x]").unwrap();
+        let matches = |filter: &str| Filter::parse(filter, 0).unwrap().matches(&record);
+        assert!(matches(r#"level == unknown && level == "<unknown>" && level ~ "^<unknown>$""#), "the unknown level is spelled as the outputs do");
+    }
+
+    #[test]
+    fn test_filter_tso() {
+        let record = LogRecordRef::parse("[2021/03/09 12:15:13.145 +08:00] [INFO] [a.rs:1] [x] [tso=410450924122144769]").unwrap();
+        let matches = |filter: &str| Filter::parse(filter, 0).unwrap().matches(&record);
+        assert!(matches("fields.tso > 5") && !matches("fields.tso < 99"), "TSOs are compared as numbers rather than strings");
+        assert!(matches("fields.tso == 410450924122144769") && !matches("fields.tso == 410450924122144768"));
+        assert!(matches("fields.tso > 410450924122144768 && fields.tso < 410450924122144770"));
+        assert!(matches("fields.tso > 1.5e3"));
+    }
+
+    #[test]
+    fn test_filter_errors() {
+        fn check(filter: &str, offset: usize, message: &str) {
+            match Filter::parse(filter, 0) {
+                Err(FilterError::Syntax { offset: o, message: m }) => {
+                    assert_eq!(o, offset, "the error offset of {}", filter);
+                    assert!(m.contains(message), "the error of {} is {}, which doesn't contain {}", filter, m, message);
+                }
+                other => panic!("unexpected result {:?} of {}", other.map(|_| ()), filter),
+            }
+        }
+
+        check("level >= verbose", 9, "unknown level verbose");
+        check("lvl >= warn", 0, "unknown path lvl");
+        check("fields. == 1", 0, "unknown path");
+        check("level >=", 8, "got the end");
+        check("(level >= warn", 14, "expecting `)`");
+        check("level >= warn level", 14, "unexpected");
+        check("message ~ \"unterminated", 10, "unterminated string");
+        check("time > yesterday", 7, "invalid time");
+        check("fields.a in 1", 12, "expecting `(`");
+        assert!(matches!(Filter::parse("message ~ \"(\"", 0), Err(FilterError::Regex(_))));
+    }
+}
@@ -0,0 +1,383 @@
+use std::{collections::HashSet, fs::{self, File}, io::{self, BufRead, BufReader, Cursor, Read, Seek, SeekFrom}, path::{Path, PathBuf}};
+use bzip2::bufread::MultiBzDecoder;
+use flate2::bufread::MultiGzDecoder;
+use quick_error::quick_error;
+
+use crate::parser::{artifacts::{TimeRef, is_record_header}, time::Timestamp};
+
+quick_error! {
+    #[derive(Debug)]
+    pub enum InputError {
+        Io(path: PathBuf, err: io::Error) {
+            source(err)
+            display("failed to read {}: {}", path.display(), err)
+        }
+        Pattern(pattern: String, err: glob::PatternError) {
+            source(err)
+            display("invalid pattern {}: {}", pattern, err)
+        }
+        NoMatch(pattern: String) {
+            display("no file matches {}", pattern)
+        }
+    }
+}
+
+/// resolve the inputs given by the user to the files to read, in order.
+/// An input can be a path of file, a directory (all files in it, recursively, sorted by path),
+/// or a shell-style glob like `logs/tikv*.log`. A file is only read once even if it is matched many times.
+pub fn resolve(inputs: &[String]) -> Result<Vec<PathBuf>, InputError> {
+    let mut files = Vec::new();
+    for input in inputs {
+        let path = Path::new(input);
+        if path.exists() || !is_glob(input) {
+            push_path(path.to_owned(), &mut files)?;
+            continue;
+        }
+        let paths = glob::glob(input).map_err(|err| InputError::Pattern(input.clone(), err))?;
+        let before = files.len();
+        for path in paths {
+            let path = path.map_err(|err| InputError::Io(err.path().to_owned(), err.into()))?;
+            push_path(path, &mut files)?;
+        }
+        if files.len() == before {
+            return Err(InputError::NoMatch(input.clone()));
+        }
+    }
+    let mut seen = HashSet::new();
+    files.retain(|path| seen.insert(path.clone()));
+    Ok(files)
+}
+
+/// name the instance each file comes from, by its path relative to the common directory of all files.
+/// The rotation timestamp, the compression and the `.log` extensions are stripped, so the rotated files
+/// share the name of the live one, e.g. `bundle/tikv-1/tikv-2026-10-15T10-00-00.000.log.gz` is `tikv-1/tikv`
+/// when it is merged with `bundle/tikv-2/tikv.log`.
+pub fn instance_names(files: &[PathBuf]) -> Vec<String> {
+    let mut common = files.first().and_then(|f| f.parent()).unwrap_or_else(|| Path::new(""));
+    for file in files {
+        while !file.starts_with(common) {
+            common = common.parent().unwrap_or_else(|| Path::new(""));
+        }
+    }
+    files
+        .iter()
+        .map(|file| {
+            let relative = file.strip_prefix(common).unwrap_or(file);
+            let name = relative.file_name().map(|n| n.to_string_lossy()).unwrap_or_default();
+            let name = unrotated(&name);
+            match relative.parent() {
+                Some(dir) if dir != Path::new("") => format!("{}/{}", dir.to_string_lossy(), name),
+                _ => name.to_owned(),
+            }
+        })
+        .collect()
+}
+
+/// strip the rotation timestamp and the extensions of a log file name,
+/// the rotated files are named as `tikv-2026-10-15T10-00-00.000.log` or `tikv.log.2026-10-15-10:00:00.000`.
+fn unrotated(name: &str) -> &str {
+    let mut name = name;
+    for ext in [".gz", ".zst", ".bz2"] {
+        name = name.strip_suffix(ext).unwrap_or(name);
+    }
+    if let Some(i) = name.find(".log") {
+        name = &name[..i];
+    }
+    let rotated_at = name.char_indices().find(|(i, c)| *c == '-' && name[i + 1..].starts_with(|c: char| c.is_ascii_digit()) && name[i + 1..].contains('T'));
+    match rotated_at {
+        Some((i, _)) => &name[..i],
+        None => name,
+    }
+}
+
+/// open the file for reading, decompressing it if it is compressed.
+pub fn open(path: &Path) -> Result<Box<dyn BufRead>, InputError> {
+    let file = File::open(path).map_err(|err| InputError::Io(path.to_owned(), err))?;
+    decompress(BufReader::new(file)).map_err(|err| InputError::Io(path.to_owned(), err))
+}
+
+/// open the file for reading from the first record which isn't `before` the time,
+/// by binary searching the records if the file isn't compressed. See `seek_records`.
+/// Returns the input with the number of lines skipped, so the line numbers still count from the start of the file.
+pub fn open_since(path: &Path, before: impl Fn(&Timestamp) -> bool) -> Result<(Box<dyn BufRead>, usize), InputError> {
+    let on_error = |err| InputError::Io(path.to_owned(), err);
+    let file = File::open(path).map_err(on_error)?;
+    let mut input = BufReader::new(file);
+    let magic = read_magic(&mut input).map_err(on_error)?;
+    input.seek(SeekFrom::Start(0)).map_err(on_error)?;
+    let mut skipped_lines = 0;
+    if Compression::detect(&magic) == Compression::None {
+        let offset = seek_records(&mut input, before).map_err(on_error)?;
+        input.seek(SeekFrom::Start(0)).map_err(on_error)?;
+        skipped_lines = count_lines(&mut input, offset).map_err(on_error)?;
+    }
+    Ok((decompress(input).map_err(on_error)?, skipped_lines))
+}
+
+/// count the lines in the first `len` bytes of the input, which is left right after them.
+/// Counting is much cheaper than parsing the records skipped.
+fn count_lines(input: &mut impl BufRead, len: u64) -> io::Result<usize> {
+    let mut input = input.take(len);
+    let mut lines = 0;
+    loop {
+        let buf = input.fill_buf()?;
+        if buf.is_empty() {
+            return Ok(lines);
+        }
+        lines += buf.iter().filter(|&&b| b == b'\n').count();
+        let n = buf.len();
+        input.consume(n);
+    }
+}
+
+/// binary search the records, which are sorted by time, of a seekable input for the first record which isn't `before`
+/// the time, and seek to there. Each probed offset is resynced to the next record header.
+/// The position sought is at or before the record, the records before it should still be filtered.
+pub fn seek_records<R: BufRead + Seek>(input: &mut R, before: impl Fn(&Timestamp) -> bool) -> io::Result<u64> {
+    /// stop binary searching when the range is small enough to be scanned.
+    const SCAN_SIZE: u64 = 64 * 1024;
+
+    let (mut lo, mut hi) = (0, input.seek(SeekFrom::End(0))?);
+    while hi - lo > SCAN_SIZE {
+        let mid = lo + (hi - lo) / 2;
+        match next_record_header(input, mid, hi)? {
+            Some((offset, timestamp)) if before(&timestamp) => lo = offset,
+            _ => hi = mid,
+        }
+    }
+    input.seek(SeekFrom::Start(lo))
+}
+
+/// find the first record header starts in `(from, limit)`, with its offset and timestamp.
+fn next_record_header<R: BufRead + Seek>(input: &mut R, from: u64, limit: u64) -> io::Result<Option<(u64, Timestamp)>> {
+    /// the longest header is `[yyyy/MM/dd HH:mm:ss.SSSSSSSSS +hh:mm]`.
+    const HEADER_SIZE: usize = 40;
+
+    let mut offset = input.seek(SeekFrom::Start(from))?;
+    let mut line = Vec::new();
+    // skip the rest of the line where `from` is.
+    offset += input.read_until(b'\n', &mut line)? as u64;
+    while offset < limit {
+        line.clear();
+        let n = input.read_until(b'\n', &mut line)?;
+        if n == 0 {
+            break;
+        }
+        let header = String::from_utf8_lossy(&line[..n.min(HEADER_SIZE)]);
+        if is_record_header(&header) {
+            if let Ok(time) = TimeRef::parse(&header) {
+                return Ok(Some((offset, time.timestamp)));
+            }
+        }
+        offset += n as u64;
+    }
+    Ok(None)
+}
+
+/// Compression is the compression format of an input.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    None,
+    Gzip,
+    Zstd,
+    Bzip2,
+}
+
+impl Compression {
+    /// the length of the longest magic bytes.
+    const MAGIC_LEN: usize = 4;
+
+    /// detect the compression format by the magic bytes at the beginning of the input.
+    pub fn detect(magic: &[u8]) -> Self {
+        if magic.starts_with(&[0x1f, 0x8b]) {
+            Compression::Gzip
+        } else if magic.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+            Compression::Zstd
+        } else if magic.starts_with(b"BZh") {
+            Compression::Bzip2
+        } else {
+            Compression::None
+        }
+    }
+}
+
+/// read the magic bytes at the beginning of the input, which are shorter only if the input ends.
+/// A single read may return fewer bytes, i.e. from a pipe, so it reads till there are enough.
+fn read_magic(input: &mut impl Read) -> io::Result<Vec<u8>> {
+    let mut magic = Vec::with_capacity(Compression::MAGIC_LEN);
+    input.take(Compression::MAGIC_LEN as u64).read_to_end(&mut magic)?;
+    Ok(magic)
+}
+
+/// wrap the input with the decoder of its compression format, the input is returned as is if it isn't compressed.
+/// Concatenated gzip and bzip2 streams are read as a whole.
+pub fn decompress<R: BufRead + 'static>(mut input: R) -> io::Result<Box<dyn BufRead>> {
+    let magic = read_magic(&mut input)?;
+    let compression = Compression::detect(&magic);
+    // the magic bytes are read already, so put them back before the rest.
+    let input = Cursor::new(magic).chain(input);
+    let input: Box<dyn BufRead> = match compression {
+        Compression::None => Box::new(input),
+        Compression::Gzip => Box::new(BufReader::new(MultiGzDecoder::new(input))),
+        Compression::Zstd => Box::new(BufReader::new(zstd::Decoder::with_buffer(input)?)),
+        Compression::Bzip2 => Box::new(BufReader::new(MultiBzDecoder::new(input))),
+    };
+    Ok(input)
+}
+
+fn is_glob(input: &str) -> bool {
+    input.contains(['*', '?', '['])
+}
+
+fn push_path(path: PathBuf, files: &mut Vec<PathBuf>) -> Result<(), InputError> {
+    let metadata = fs::metadata(&path).map_err(|err| InputError::Io(path.clone(), err))?;
+    if !metadata.is_dir() {
+        files.push(path);
+        return Ok(());
+    }
+    let mut entries = fs::read_dir(&path)
+        .and_then(|dir| dir.map(|entry| entry.map(|e| e.path())).collect::<io::Result<Vec<_>>>())
+        .map_err(|err| InputError::Io(path.clone(), err))?;
+    entries.sort();
+    for entry in entries {
+        push_path(entry, files)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, io::{BufRead, Cursor, Read, Write}, path::PathBuf};
+
+    use super::{Compression, InputError, count_lines, decompress, instance_names, resolve, seek_records};
+    use crate::parser::artifacts::TimeRef;
+
+    #[test]
+    fn test_resolve() {
+        let root = std::env::temp_dir().join(format!("tidc-test-resolve-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        for file in ["tikv.log", "tikv-2026-10-15T10-00-00.000.log", "pd.log", "bundle/tikv-1/tikv.log", "bundle/tikv-0/tikv.log"] {
+            let path = root.join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, "").unwrap();
+        }
+        let input = |s: &str| root.join(s).to_string_lossy().into_owned();
+        let relative = |files: Vec<PathBuf>| files.iter().map(|f| f.strip_prefix(&root).unwrap().to_string_lossy().into_owned()).collect::<Vec<_>>();
+
+        assert_eq!(relative(resolve(&[input("tikv*.log"), input("pd.log"), input("tikv.log")]).unwrap()), vec![
+            "tikv-2026-10-15T10-00-00.000.log", "tikv.log", "pd.log",
+        ]);
+        assert_eq!(relative(resolve(&[input("bundle")]).unwrap()), vec!["bundle/tikv-0/tikv.log", "bundle/tikv-1/tikv.log"]);
+        assert_eq!(relative(resolve(&[input("bundle/*/tikv.log")]).unwrap()), vec!["bundle/tikv-0/tikv.log", "bundle/tikv-1/tikv.log"]);
+        assert!(matches!(resolve(&[input("tidb*.log")]), Err(InputError::NoMatch(_))));
+        assert!(matches!(resolve(&[input("tidb.log")]), Err(InputError::Io(..))));
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_instance_names() {
+        fn check(files: &[&str], names: &[&str]) {
+            let files = files.iter().map(PathBuf::from).collect::<Vec<_>>();
+            assert_eq!(instance_names(&files), names, "failed to name {:?}", files);
+        }
+
+        check(&["logs/tikv.log"], &["tikv"]);
+        check(&["logs/tikv.log", "logs/tikv-2026-10-15T10-00-00.000.log.gz", "logs/tikv.log.2026-10-15-10:00:00.000", "logs/pd.log", "logs/tidb-slow.log"], &[
+            "tikv", "tikv", "tikv", "pd", "tidb-slow",
+        ]);
+        check(&["bundle/tikv-1/tikv.log", "bundle/tikv-2/tikv-2026-10-15T10-00-00.000.log.zst", "bundle/pd-1/log/pd.log"], &[
+            "tikv-1/tikv", "tikv-2/tikv", "pd-1/log/pd",
+        ]);
+        check(&["tikv.log", "/var/log/tikv.log"], &["tikv", "/var/log/tikv"]);
+    }
+
+    #[test]
+    fn test_decompress() {
+        const LOG: &str = "[2018/12/15 14:20:11.015 +08:00] [INFO] [tikv-server.rs:13] [\"TiKV Started\"]\n";
+
+        fn gzip(s: &str) -> Vec<u8> {
+            let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+            encoder.write_all(s.as_bytes()).unwrap();
+            encoder.finish().unwrap()
+        }
+        fn bzip2(s: &str) -> Vec<u8> {
+            let mut encoder = bzip2::write::BzEncoder::new(Vec::new(), bzip2::Compression::default());
+            encoder.write_all(s.as_bytes()).unwrap();
+            encoder.finish().unwrap()
+        }
+        fn check(compressed: Vec<u8>, compression: Compression, expected: &str) {
+            assert_eq!(Compression::detect(&compressed), compression);
+            let mut decompressed = String::new();
+            decompress(Cursor::new(compressed)).unwrap().read_to_string(&mut decompressed).unwrap();
+            assert_eq!(decompressed, expected);
+        }
+
+        check(LOG.as_bytes().to_vec(), Compression::None, LOG);
+        check(Vec::new(), Compression::None, "");
+        check(gzip(LOG), Compression::Gzip, LOG);
+        check([gzip(LOG), gzip(LOG)].concat(), Compression::Gzip, &LOG.repeat(2));
+        check(zstd::encode_all(LOG.as_bytes(), 0).unwrap(), Compression::Zstd, LOG);
+        check(bzip2(LOG), Compression::Bzip2, LOG);
+
+        // a pipe may give fewer bytes than the magic bytes at a time.
+        struct Trickle<R>(R);
+        impl<R: Read> Read for Trickle<R> {
+            fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+                let len = buf.len().min(1);
+                self.0.read(&mut buf[..len])
+            }
+        }
+        for compressed in [zstd::encode_all(LOG.as_bytes(), 0).unwrap(), bzip2(LOG), gzip(LOG), LOG.as_bytes().to_vec()] {
+            let mut decompressed = String::new();
+            let input = std::io::BufReader::with_capacity(1, Trickle(Cursor::new(compressed)));
+            decompress(input).unwrap().read_to_string(&mut decompressed).unwrap();
+            assert_eq!(decompressed, LOG);
+        }
+    }
+
+    #[test]
+    fn test_seek_records() {
+        let mut log = String::new();
+        for i in 0..20000 {
+            log.push_str(&format!("[2021/03/09 12:{:02}:{:02}.{:03} +08:00] [INFO] [a.rs:1] [\"record {}\"]\n", i / 6000, i / 100 % 60, i % 100 * 10, i));
+            if i % 7 == 0 {
+                log.push_str("  continuation [2021/03/09 00:00:00.000 +08:00]\n[not a header]\n");
+            }
+        }
+        let since = |s: &str| {
+            let since = TimeRef::parse(s).unwrap().timestamp;
+            move |t: &super::Timestamp| *t < since
+        };
+        let first_after = |mut input: Cursor<&[u8]>, s: &str| {
+            let before = since(s);
+            let mut skipped = 0;
+            let mut line = String::new();
+            loop {
+                line.clear();
+                assert_ne!(input.read_line(&mut line).unwrap(), 0);
+                match TimeRef::parse(&line) {
+                    Ok(time) if !before(&time.timestamp) => return (skipped, line.clone()),
+                    _ => skipped += 1,
+                }
+            }
+        };
+
+        for (s, expected) in [
+            ("[2021/03/09 12:00:00.000 +08:00]", "record 0\""),
+            ("[2021/03/09 12:01:40.500 +08:00]", "record 10050\""),
+            ("[2021/03/09 12:02:00.000 +08:00]", "record 12000\""),
+            ("[2021/03/09 12:03:19.990 +08:00]", "record 19999\""),
+        ] {
+            let mut input = Cursor::new(log.as_bytes());
+            let offset = seek_records(&mut input, since(s)).unwrap() as usize;
+            assert!(offset == 0 || log[..offset].ends_with('\n'), "{} isn't at the start of a line", offset);
+            input.set_position(0);
+            assert_eq!(count_lines(&mut input, offset as u64).unwrap(), log[..offset].lines().count());
+            assert_eq!(input.position() as usize, offset);
+            let (skipped, line) = first_after(input, s);
+            assert!(line.contains(expected), "seeking {} got {}", s, line);
+            assert!(skipped < 2000, "too many lines ({}) are skipped seeking {}", skipped, s);
+        }
+    }
+}
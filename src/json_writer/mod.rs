@@ -1,5 +1,5 @@
-use std::{io::{self, Write}};
-use crate::parser::artifacts::*;
+use std::{fmt::Display, io::{self, Write}};
+use crate::{parser::{ParseError, artifacts::*, diagnostics::SkippedField, value::TypedValue}, output::{Origin, WriteRecord}, projection::{FieldValues, Key, Projection}};
 
 pub trait ToJSON {
     fn write_json_to<W: Write>(&self, w: W) -> io::Result<()>;
@@ -41,44 +41,77 @@ impl<W:Write> JsonObjectBuilder<W> {
     }
 }
 
-impl<'a> ToJSON for &'a str {
-    fn write_json_to<W: Write>(&self, mut w: W) -> io::Result<()> {
-        w.write_fmt(format_args!("{:?}", self))
+/// write `s` as a JSON string literal, escaping it as RFC 8259 requires.
+/// Non-ASCII characters are written as-is since the output is always UTF-8.
+pub fn write_json_str<W: Write>(w: W, s: &str) -> io::Result<()> {
+    write_quoted_str(w, s, false)
+}
+
+/// write `s` quoted and escaped as a JSON string, `escape_del` also escapes the DEL char (0x7f),
+/// which JSON allows as-is but is invisible on terminals.
+pub(crate) fn write_quoted_str<W: Write>(mut w: W, s: &str, escape_del: bool) -> io::Result<()> {
+    const HEX: &[u8; 16] = b"0123456789abcdef";
+
+    w.write_all(b"\"")?;
+    let bytes = s.as_bytes();
+    let mut start = 0;
+    for (i, &b) in bytes.iter().enumerate() {
+        let unicode_escaped;
+        let escaped: &[u8] = match b {
+            b'"' => b"\\\"",
+            b'\\' => b"\\\\",
+            b'\n' => b"\\n",
+            b'\r' => b"\\r",
+            b'\t' => b"\\t",
+            0x08 => b"\\b",
+            0x0c => b"\\f",
+            0x7f if escape_del => b"\\u007f",
+            0x00..=0x1f => {
+                unicode_escaped = [b'\\', b'u', b'0', b'0', HEX[(b >> 4) as usize], HEX[(b & 0xf) as usize]];
+                &unicode_escaped
+            }
+            _ => continue,
+        };
+        w.write_all(&bytes[start..i])?;
+        w.write_all(escaped)?;
+        start = i + 1;
+    }
+    w.write_all(&bytes[start..])?;
+    w.write_all(b"\"")
+}
+
+impl ToJSON for &str {
+    fn write_json_to<W: Write>(&self, w: W) -> io::Result<()> {
+        write_json_str(w, self)
     }
 }
 
 impl <'a> ToJSON for LogStr<'a> {
-    fn write_json_to<W: Write>(&self, mut w: W) -> io::Result<()> {
+    fn write_json_to<W: Write>(&self, w: W) -> io::Result<()> {
         match self {
-            Self::Quoted(s) => w.write_all(s.as_bytes()),
-            Self::Unquoted(s) => {
-                w.write_all("\"".as_bytes())?;
-                w.write_all(s.as_bytes())?;
-                w.write_all("\"".as_bytes())
-            }
+            Self::Quoted(_) => write_json_str(w, &self.unescape()),
+            Self::Unquoted(s) => write_json_str(w, s),
         }
     }
 }
 
-impl <'a> ToJSON for LogLevel {
+impl ToJSON for usize {
+    fn write_json_to<W: Write>(&self, mut w: W) -> io::Result<()> {
+        write!(w, "{}", self)
+    }
+}
+
+impl ToJSON for LogLevel {
     fn write_json_to<W: Write>(&self, w: W) -> io::Result<()> {
-        let desc = match self {
-            LogLevel::Debug => "debug",
-            LogLevel::Info => "info",
-            LogLevel::Warn => "warn",
-            LogLevel::Error => "error",
-            LogLevel::Fatal => "fatal",
-            LogLevel::Unknown => "<unknown>",
-        };
-        desc.write_json_to(w)
+        self.name().write_json_to(w)
     }
 }
 
 impl <'a> ToJSON for FileLineRef<'a> {
     fn write_json_to<W: Write>(&self, w: W) -> io::Result<()> {
         let mut builder = JsonObjectBuilder::on_writer(w)?;
-        builder.write_field("file", self.file)?;
-        builder.write_field("line", self.line)?;
+        builder.write_field("file", &*self.file)?;
+        builder.write_field("line", &*self.line)?;
         builder.end()?;
         Ok(())
     }
@@ -100,17 +133,211 @@ impl <T: ToJSON> ToJSON for &T {
     }
 }
 
-impl <'a> ToJSON for LogRecordRef<'a> {
-    fn write_json_to<W: Write>(&self, w: W) -> io::Result<()> {
+/// TypedValues is how the field values with an inferred type are written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TypedValues {
+    /// write all values as strings.
+    #[default]
+    Off,
+    /// write numbers and booleans as they are, and durations, sizes and rates as numbers
+    /// in seconds, bytes and bytes per second.
+    Number,
+    /// like `Number`, but durations, sizes and rates are written as `{"value": .., "unit": .., "raw": ..}`.
+    Object,
+}
+
+/// Flatten is how the fields and the source are put at the top level of records.
+#[derive(Debug, Clone)]
+pub struct Flatten {
+    /// put the fields at the top level, with their keys prefixed, like `fields.sql` or `f_sql`.
+    /// A field colliding with a built-in key is written as `fields.<key>` instead.
+    pub fields: Option<String>,
+    /// write the `file` and `line` of the source at the top level.
+    pub source: bool,
+}
+
+/// JsonFormat is the options of how a record is written.
+#[derive(Debug, Clone, Default)]
+pub struct JsonFormat {
+    typed_values: TypedValues,
+    projection: Projection,
+    flatten: Option<Flatten>,
+}
+
+impl JsonFormat {
+    pub fn typed_values(mut self, typed_values: TypedValues) -> Self {
+        self.typed_values = typed_values;
+        self
+    }
+
+    pub fn flatten(mut self, flatten: Flatten) -> Self {
+        self.flatten = Some(flatten);
+        self
+    }
+
+    /// only write the selected keys, with their new names.
+    pub fn projection(mut self, projection: Projection) -> Self {
+        self.projection = projection;
+        self
+    }
+
+    pub fn write_record<W: Write>(&self, record: &LogRecordRef<'_>, w: W) -> io::Result<()> {
+        self.write_record_from(record, Origin::default(), w)
+    }
+
+    /// write the record with the `_file` and `_line` attributes of where it comes from.
+    pub fn write_record_from<W: Write>(&self, record: &LogRecordRef<'_>, origin: Origin<'_>, w: W) -> io::Result<()> {
         let mut builder = JsonObjectBuilder::on_writer(w)?;
-        builder.write_field("message", &self.message)?;
-        builder.write_field("level", &self.level)?;
-        builder.write_field("source", &self.source)?;
-        builder.write_field("time", &self.time)?;
-        builder.write_field("fields", self.entries.as_slice())?;
+        let keys = self.projection.keys();
+        let flatten_source = self.flatten.as_ref().is_some_and(|flatten| flatten.source);
+        let field_prefix = self.flatten.as_ref().and_then(|flatten| flatten.fields.as_deref());
+        // the fields selected one by one are written together, at where the first of them is.
+        let mut fields_written = false;
+        for key in keys {
+            let name = self.projection.name(key);
+            match key {
+                Key::Message => builder.write_field(name, &record.message)?,
+                Key::Level => builder.write_field(name, record.level)?,
+                Key::Source if flatten_source => {
+                    builder.write_field("file", record.source.as_ref().map(|source| &*source.file))?;
+                    builder.write_field("line", record.source.as_ref().map(|source| &*source.line))?;
+                }
+                Key::Source => builder.write_field(name, &record.source)?,
+                Key::Time => builder.write_field(name, &record.time)?,
+                Key::Fields | Key::Field(_) if fields_written => {}
+                Key::Fields | Key::Field(_) => {
+                    fields_written = true;
+                    match field_prefix {
+                        Some(prefix) => self.write_flattened_fields(&mut builder, &record.entries, prefix, flatten_source)?,
+                        None => {
+                            builder.write_key(self.projection.name(&Key::Fields))?;
+                            self.write_fields(&record.entries, Origin::default(), &mut builder.write)?;
+                        }
+                    }
+                }
+                key => Self::write_origin(&mut builder, name, key, origin)?,
+            }
+        }
         builder.end()?;
         Ok(())
     }
+
+    /// write the fields into the record with the prefix, resolving collisions with the built-in keys.
+    fn write_flattened_fields<W: Write>(&self, builder: &mut JsonObjectBuilder<W>, fields: &[LogFieldRef<'_>], prefix: &str, flatten_source: bool) -> io::Result<()> {
+        let mut reserved = self.projection.keys().iter()
+            .filter(|key| !(matches!(key, Key::Fields | Key::Field(_)) || flatten_source && **key == Key::Source))
+            .map(|key| self.projection.name(key))
+            .collect::<Vec<_>>();
+        if flatten_source && self.projection.keys().contains(&Key::Source) {
+            reserved.extend(["file", "line"]);
+        }
+        let mut name = String::new();
+        for field in self.projection.fields(fields) {
+            name.clear();
+            name.push_str(prefix);
+            name.push_str(&field.name);
+            if reserved.contains(&name.as_str()) {
+                name.clear();
+                name.push_str("fields.");
+                name.push_str(&field.name);
+            }
+            builder.write_key(name.as_str())?;
+            self.write_values(&field.values, &mut builder.write)?;
+        }
+        Ok(())
+    }
+
+    /// write the fields as an object, the `_file` and `_line` attributes are written as fields.
+    pub fn write_fields<W: Write>(&self, fields: &[LogFieldRef<'_>], origin: Origin<'_>, w: W) -> io::Result<()> {
+        let mut builder = JsonObjectBuilder::on_writer(w)?;
+        for field in self.projection.fields(fields) {
+            builder.write_key(field.name.as_ref())?;
+            self.write_values(&field.values, &mut builder.write)?;
+        }
+        for key in self.projection.keys() {
+            Self::write_origin(&mut builder, self.projection.name(key), key, origin)?;
+        }
+        builder.end()
+    }
+
+    fn write_values<W: Write>(&self, values: &FieldValues<'_, '_>, mut w: W) -> io::Result<()> {
+        match values {
+            FieldValues::One(value) => self.write_value(value, w),
+            FieldValues::Many(values) => {
+                w.write_all(b"[")?;
+                for (i, value) in values.iter().enumerate() {
+                    if i > 0 {
+                        w.write_all(b",")?;
+                    }
+                    self.write_value(value, &mut w)?;
+                }
+                w.write_all(b"]")
+            }
+        }
+    }
+
+    /// write the `_instance`, `_file` or `_line` attribute if present, other keys are ignored.
+    fn write_origin<W: Write>(builder: &mut JsonObjectBuilder<W>, name: &str, key: &Key, origin: Origin<'_>) -> io::Result<()> {
+        match key {
+            Key::Instance => if let Some(instance) = origin.instance {
+                builder.write_field(name, instance)?;
+            },
+            Key::File => if let Some(file) = origin.file {
+                builder.write_field(name, file)?;
+            },
+            Key::Line => if let Some(line) = origin.line {
+                builder.write_field(name, line)?;
+            },
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn write_value<W: Write>(&self, value: &LogStr<'_>, mut w: W) -> io::Result<()> {
+        if self.typed_values == TypedValues::Off {
+            return value.write_json_to(w);
+        }
+        let text = value.unescape();
+        match TypedValue::infer(&text) {
+            None => write_json_str(w, &text),
+            Some(typed) => match typed.unit() {
+                Some(unit) if self.typed_values == TypedValues::Object => {
+                    let mut builder = JsonObjectBuilder::on_writer(w)?;
+                    builder.write_field("value", typed)?;
+                    builder.write_field("unit", unit)?;
+                    builder.write_field("raw", text.as_ref())?;
+                    builder.end()
+                }
+                _ => typed.write_json_to(&mut w),
+            },
+        }
+    }
+}
+
+impl WriteRecord for JsonFormat {
+    fn write_record(&mut self, record: &LogRecordRef<'_>, origin: Origin<'_>, w: &mut dyn Write) -> io::Result<()> {
+        self.write_record_from(record, origin, &mut *w)?;
+        writeln!(w)
+    }
+}
+
+impl ToJSON for TypedValue {
+    fn write_json_to<W: Write>(&self, mut w: W) -> io::Result<()> {
+        match self {
+            TypedValue::Int(n) => write!(w, "{}", n),
+            TypedValue::Bool(b) => write!(w, "{}", b),
+            TypedValue::Float(f)
+            | TypedValue::Duration { seconds: f }
+            | TypedValue::Size { bytes: f }
+            | TypedValue::Rate { bytes_per_second: f } => write!(w, "{}", f),
+        }
+    }
+}
+
+impl <'a> ToJSON for LogRecordRef<'a> {
+    fn write_json_to<W: Write>(&self, w: W) -> io::Result<()> {
+        JsonFormat::default().write_record(self, w)
+    }
 }
 
 impl <'a> ToJSON for &[LogFieldRef<'a>] {
@@ -124,8 +351,223 @@ impl <'a> ToJSON for &[LogFieldRef<'a>] {
     }
 }
 
+/// the unknown time is written as `null`.
 impl <'a> ToJSON for TimeRef<'a> {
     fn write_json_to<W: Write>(&self, mut w: W) -> io::Result<()> {
-        w.write_fmt(format_args!("{:?}", self.time_str))
+        if self.is_unknown() {
+            return w.write_all(b"null");
+        }
+        write_json_str(w, self.time_str)
+    }
+}
+
+/// Passthrough is a line failed to parse, which is written as `{"_raw": ..., "_error": ...}`.
+pub struct Passthrough<'a, E> {
+    pub raw: &'a str,
+    pub error: &'a E,
+}
+
+impl <'a, E: Display> ToJSON for Passthrough<'a, E> {
+    fn write_json_to<W: Write>(&self, w: W) -> io::Result<()> {
+        let mut builder = JsonObjectBuilder::on_writer(w)?;
+        builder.write_field("_raw", self.raw)?;
+        builder.write_field("_error", self.error.to_string().as_str())?;
+        builder.end()?;
+        Ok(())
+    }
+}
+
+impl <'a> ToJSON for SkippedField<'a> {
+    fn write_json_to<W: Write>(&self, w: W) -> io::Result<()> {
+        let mut builder = JsonObjectBuilder::on_writer(w)?;
+        builder.write_field("diagnostic", "skipped field")?;
+        builder.write_field("field", self.text)?;
+        builder.write_field("error", self.error.to_string().as_str())?;
+        if let ParseError::Unexpected { expected, got, offset, line, component, .. } = &self.error {
+            builder.write_field("expected", expected.as_str())?;
+            builder.write_field("got", got.as_str())?;
+            builder.write_field("line", line)?;
+            builder.write_field("offset", offset)?;
+            builder.write_field("component", component.to_string().as_str())?;
+        }
+        builder.end()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parser::artifacts::{LogRecordRef, TimeRef, with_log_record};
+
+    use crate::projection::{DuplicateKeys, Key, Projection};
+
+    use crate::output::Origin;
+
+    use super::{Flatten, JsonFormat, ToJSON, TypedValues, write_json_str};
+
+    fn encode(s: &str) -> String {
+        let mut buf = Vec::new();
+        write_json_str(&mut buf, s).unwrap();
+        String::from_utf8(buf).unwrap()
+    }
+
+    #[test]
+    fn test_json_str() {
+        fn check(from: &str, to: &str) {
+            assert_eq!(encode(from), to, "failed to encode {:?}", from);
+        }
+
+        check("", r#""""#);
+        check("TiKV Started", r#""TiKV Started""#);
+        check(r#"SELECT * FROM TABLE WHERE ID="abc""#, r#""SELECT * FROM TABLE WHERE ID=\"abc\"""#);
+        check(r"C:\tikv\data\", r#""C:\\tikv\\data\\""#);
+        check(
+            "   0: std::panicking::default_hook::{{closure}}\n\tat /checkout/src/libstd/panicking.rs:381\r\n",
+            r#""   0: std::panicking::default_hook::{{closure}}\n\tat /checkout/src/libstd/panicking.rs:381\r\n""#,
+        );
+        // the colored output of some panic hooks.
+        check("\u{1b}[31merror\u{1b}[0m", r#""\u001b[31merror\u001b[0m""#);
+        check("key\u{0}\u{8}\u{c}\u{1f}\u{7f}", "\"key\\u0000\\b\\f\\u001f\u{7f}\"");
+        check("[duration=992.547µs]", r#""[duration=992.547µs]""#);
+        check(r#"emoji😈=😊👼🇺🇳╮(￣▽￣"")╭"#, r#""emoji😈=😊👼🇺🇳╮(￣▽￣\"\")╭""#);
+        check("\u{2028}\u{2029}'/", "\"\u{2028}\u{2029}'/\"");
+    }
+
+    #[test]
+    fn test_quoted_values_are_reencoded() {
+        let line = r#"[2018/12/15 14:20:11.015 +08:00] [ERROR] [panic_hook.rs:45] ["TiKV\x1b[0m panic"] [error="\x1b[31mboom\x1b[0m"] [path="C:\\tikv"] [tab="a\tb\u00e9"]"#;
+        let json = with_log_record(line, |r| {
+            let mut buf = Vec::new();
+            r.write_json_to(&mut buf).unwrap();
+            String::from_utf8(buf).unwrap()
+        }).unwrap();
+        assert_eq!(json, concat!(
+            r#"{"message":"TiKV\u001b[0m panic","level":"error","source":{"file":"panic_hook.rs","line":"45"},"#,
+            r#""time":"2018/12/15 14:20:11.015 +08:00","fields":{"error":"\u001b[31mboom\u001b[0m","path":"C:\\tikv","tab":"a\tbé"}}"#,
+        ));
+    }
+
+    #[test]
+    fn test_typed_values() {
+        let line = r#"[2021/03/09 12:15:13.145 +08:00] [INFO] [backup.rs:1] ["backup range finished"] [take=992.547µs] [elapsed=1m3s] [speed=128MB/s] [size=1.2GiB] [count=42] [ratio=0.5] [ok=true] [ts=418928178437472257] [store="1"] [addr=127.0.0.1:20160]"#;
+        let write = |typed_values| with_log_record(line, |r| {
+            let mut buf = Vec::new();
+            JsonFormat::default().typed_values(typed_values).write_record(&r, &mut buf).unwrap();
+            String::from_utf8(buf).unwrap()
+        }).unwrap();
+        let fields = |json: String| json[json.find(r#""fields":"#).unwrap()..].to_owned();
+
+        assert_eq!(fields(write(TypedValues::Off)), concat!(
+            r#""fields":{"take":"992.547µs","elapsed":"1m3s","speed":"128MB/s","size":"1.2GiB","count":"42","ratio":"0.5","#,
+            r#""ok":"true","ts":"418928178437472257","store":"1","addr":"127.0.0.1:20160"}}"#,
+        ));
+        assert_eq!(fields(write(TypedValues::Number)), concat!(
+            r#""fields":{"take":0.000992547,"elapsed":63,"speed":134217728,"size":1288490188.8,"count":42,"ratio":0.5,"#,
+            r#""ok":true,"ts":"418928178437472257","store":1,"addr":"127.0.0.1:20160"}}"#,
+        ));
+        assert_eq!(fields(write(TypedValues::Object)), concat!(
+            r#""fields":{"take":{"value":0.000992547,"unit":"s","raw":"992.547µs"},"elapsed":{"value":63,"unit":"s","raw":"1m3s"},"#,
+            r#""speed":{"value":134217728,"unit":"B/s","raw":"128MB/s"},"size":{"value":1288490188.8,"unit":"B","raw":"1.2GiB"},"#,
+            r#""count":42,"ratio":0.5,"ok":true,"ts":"418928178437472257","store":1,"addr":"127.0.0.1:20160"}}"#,
+        ));
+    }
+
+    #[test]
+    fn test_origin() {
+        let line = r#"[2021/03/09 12:15:13.145 +08:00] [INFO] [a.rs:1] ["x"] [a=1]"#;
+        let json = with_log_record(line, |r| {
+            let mut buf = Vec::new();
+            let origin = Origin { instance: None, file: Some("logs/tikv.log"), line: Some(42) };
+            JsonFormat::default().write_record_from(&r, origin, &mut buf).unwrap();
+            String::from_utf8(buf).unwrap()
+        }).unwrap();
+        assert!(json.ends_with(r#""fields":{"a":"1"},"_file":"logs/tikv.log","_line":42}"#), "{}", json);
+
+        let json = with_log_record(line, |r| {
+            let mut buf = Vec::new();
+            LogRecordRef { time: TimeRef::UNKNOWN, ..r }.write_json_to(&mut buf).unwrap();
+            String::from_utf8(buf).unwrap()
+        }).unwrap();
+        assert!(json.contains(r#""time":null"#), "{}", json);
+    }
+
+    #[test]
+    fn test_flatten() {
+        let line = r#"[2021/03/09 12:15:13.145 +08:00] [INFO] [a.rs:1] ["x"] [sql="select 1"] [message=m] [file=f] [region_id=2]"#;
+        let write = |format: JsonFormat| with_log_record(line, |r| {
+            let mut buf = Vec::new();
+            format.write_record(&r, &mut buf).unwrap();
+            String::from_utf8(buf).unwrap()
+        }).unwrap();
+        let flatten = |prefix: &str, source| Flatten { fields: Some(prefix.to_owned()), source };
+
+        assert_eq!(
+            write(JsonFormat::default().flatten(flatten("fields.", false))),
+            concat!(
+                r#"{"message":"x","level":"info","source":{"file":"a.rs","line":"1"},"time":"2021/03/09 12:15:13.145 +08:00","#,
+                r#""fields.sql":"select 1","fields.message":"m","fields.file":"f","fields.region_id":"2"}"#,
+            ),
+        );
+        assert_eq!(
+            write(JsonFormat::default().flatten(flatten("", true)).typed_values(TypedValues::Number)),
+            concat!(
+                r#"{"message":"x","level":"info","file":"a.rs","line":"1","time":"2021/03/09 12:15:13.145 +08:00","#,
+                r#""sql":"select 1","fields.message":"m","fields.file":"f","region_id":2}"#,
+            ),
+        );
+        let projection = Projection::default()
+            .select(vec![Key::Field("sql".to_owned()), Key::Time, Key::Field("region_id".to_owned())])
+            .rename(Key::Field("region_id".to_owned()), "region");
+        assert_eq!(
+            write(JsonFormat::default().flatten(flatten("f_", true)).projection(projection)),
+            r#"{"f_sql":"select 1","f_region":"2","time":"2021/03/09 12:15:13.145 +08:00"}"#,
+        );
+        let flatten_source = Flatten { fields: None, source: true };
+        assert!(write(JsonFormat::default().flatten(flatten_source)).starts_with(r#"{"message":"x","level":"info","file":"a.rs","line":"1","#));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_duplicate_keys() {
+        let line = r#"[2021/03/09 12:15:13.145 +08:00] [INFO] [a.rs:1] ["x"] [a=1] [b=2] [a=3s]"#;
+        let write = |format: JsonFormat| with_log_record(line, |r| {
+            let mut buf = Vec::new();
+            format.write_fields(&r.entries, Origin::default(), &mut buf).unwrap();
+            String::from_utf8(buf).unwrap()
+        }).unwrap();
+        let policy = |duplicate_keys| JsonFormat::default().projection(Projection::default().duplicate_keys(duplicate_keys));
+
+        assert_eq!(write(policy(DuplicateKeys::All)), r#"{"a":"1","b":"2","a":"3s"}"#);
+        assert_eq!(write(policy(DuplicateKeys::Array)), r#"{"a":["1","3s"],"b":"2"}"#);
+        assert_eq!(write(policy(DuplicateKeys::Array).typed_values(TypedValues::Number)), r#"{"a":[1,3],"b":2}"#);
+        assert_eq!(write(policy(DuplicateKeys::Suffix)), r#"{"a":"1","b":"2","a_2":"3s"}"#);
+    }
+
+    #[test]
+    fn test_projection() {
+        let line = r#"[2021/03/09 12:15:13.145 +08:00] [INFO] [a.rs:1] ["x"] [region_id=2] [a=1] [region_id=3] [b=2]"#;
+        let write = |projection| with_log_record(line, |r| {
+            let mut buf = Vec::new();
+            let origin = Origin { instance: None, file: Some("tikv.log"), line: Some(42) };
+            JsonFormat::default().projection(projection).write_record_from(&r, origin, &mut buf).unwrap();
+            String::from_utf8(buf).unwrap()
+        }).unwrap();
+        let keys = |s: &str| s.split(',').map(|k| Key::parse(k).unwrap()).collect::<Vec<_>>();
+
+        assert_eq!(
+            write(Projection::default().select(keys("time,message,fields.b,_line,fields.region_id"))),
+            r#"{"time":"2021/03/09 12:15:13.145 +08:00","message":"x","fields":{"b":"2","region_id":"2","region_id":"3"},"_line":42}"#,
+        );
+        assert_eq!(
+            write(Projection::default().select(keys("level,fields")).rename(Key::Field("region_id".to_owned()), "region").rename(Key::Fields, "f")),
+            r#"{"level":"info","f":{"region":"2","a":"1","region":"3","b":"2"}}"#,
+        );
+        assert_eq!(
+            write(Projection::default().select(keys("message,fields.c"))),
+            r#"{"message":"x","fields":{}}"#,
+        );
+        assert_eq!(
+            write(Projection::default().rename(Key::Message, "msg").rename(Key::File, "file")),
+            r#"{"msg":"x","level":"info","source":{"file":"a.rs","line":"1"},"time":"2021/03/09 12:15:13.145 +08:00","fields":{"region_id":"2","a":"1","region_id":"3","b":"2"},"file":"tikv.log","_line":42}"#,
+        );
+    }
+}
@@ -1,8 +1,16 @@
 pub mod parser;
 pub mod json_writer;
+pub mod input;
+pub mod merge;
+pub mod filter;
+pub mod projection;
+pub mod output;
+pub mod csv_writer;
+pub mod logfmt_writer;
+pub mod unified_log_writer;
 
 use std::io;
-use crate::parser::ParseError;
+use crate::{filter::FilterError, input::InputError, parser::{ParseError, reader::ReadError}};
 use quick_error::quick_error;
 
 quick_error! {
@@ -18,8 +26,31 @@ quick_error! {
             source(err)
             display("Error during parsing log: {}", err)
         }
+        Read(err: ReadError) {
+            source(err)
+            display("Error during reading log: {}", err)
+        }
+        Input(err: InputError) {
+            from()
+            source(err)
+            display("Error during opening input: {}", err)
+        }
+        Filter(err: FilterError) {
+            from()
+            source(err)
+            display("Error in the filter: {}", err)
+        }
         Cli(msg: String) {
             display("CLI interface error: {}", msg)
         }
     }
-}
\ No newline at end of file
+}
+
+impl From<ReadError> for Error {
+    fn from(err: ReadError) -> Self {
+        match err {
+            ReadError::Io(err) => Error::Io(err),
+            err => Error::Read(err),
+        }
+    }
+}
@@ -0,0 +1,214 @@
+use std::io::{self, Write};
+use crate::{json_writer::{ToJSON, TypedValues, write_json_str}, output::{Origin, WriteRecord}, parser::{artifacts::*, char_need_quote, value::TypedValue}, projection::{FieldValues, Key, Projection}};
+
+/// the keys written when no key is selected.
+const DEFAULT_KEYS: &[Key] = &[Key::Time, Key::Level, Key::Message, Key::Source, Key::Fields, Key::Instance, Key::File, Key::Line];
+
+/// write `s` as a value, which is quoted like a JSON string if it is empty or has any char `char_need_quote`.
+pub fn write_logfmt_value<W: Write>(mut w: W, s: &str) -> io::Result<()> {
+    if s.is_empty() || s.chars().any(char_need_quote) {
+        return write_json_str(w, s);
+    }
+    w.write_all(s.as_bytes())
+}
+
+/// write `s` as a key, keys cannot be quoted so the chars `char_need_quote` are replaced by `_`.
+pub fn write_logfmt_key<W: Write>(mut w: W, s: &str) -> io::Result<()> {
+    if !s.chars().any(char_need_quote) {
+        return w.write_all(s.as_bytes());
+    }
+    let key = s.chars().map(|c| if char_need_quote(c) { '_' } else { c }).collect::<String>();
+    w.write_all(key.as_bytes())
+}
+
+/// LogfmtFormat is the options of how a record is written as a logfmt line,
+/// like `time="2018/12/15 14:20:11.015 +08:00" level=warn msg="Slow query" source=session.go:1234 duration=1.345s`.
+/// The fields are written after the built-in keys, a field colliding with them is written as `fields.<key>`.
+#[derive(Debug, Clone, Default)]
+pub struct LogfmtFormat {
+    typed_values: TypedValues,
+    projection: Projection,
+}
+
+/// PairWriter writes the `key=value` pairs separated by spaces.
+struct PairWriter<W> {
+    initial: bool,
+    write: W,
+}
+
+impl<W: Write> PairWriter<W> {
+    fn write_key(&mut self, key: &str) -> io::Result<()> {
+        if !self.initial {
+            self.write.write_all(b" ")?;
+        }
+        self.initial = false;
+        write_logfmt_key(&mut self.write, key)?;
+        self.write.write_all(b"=")
+    }
+
+    fn write_pair(&mut self, key: &str, value: &str) -> io::Result<()> {
+        self.write_key(key)?;
+        write_logfmt_value(&mut self.write, value)
+    }
+}
+
+impl LogfmtFormat {
+    /// write numbers and booleans as they are, and durations, sizes and rates as numbers
+    /// in seconds, bytes and bytes per second if not `Off`.
+    pub fn typed_values(mut self, typed_values: TypedValues) -> Self {
+        self.typed_values = typed_values;
+        self
+    }
+
+    /// only write the selected keys, with their new names.
+    pub fn projection(mut self, projection: Projection) -> Self {
+        self.projection = projection;
+        self
+    }
+
+    fn keys(&self) -> &[Key] {
+        self.projection.selection().unwrap_or(DEFAULT_KEYS)
+    }
+
+    /// the name of the key, the message is `msg` unless it is renamed.
+    fn name<'a>(&'a self, key: &'a Key) -> &'a str {
+        match key {
+            Key::Message => self.projection.renamed(key).unwrap_or("msg"),
+            key => self.projection.name(key),
+        }
+    }
+
+    pub fn write_record_from<W: Write>(&self, record: &LogRecordRef<'_>, origin: Origin<'_>, w: W) -> io::Result<()> {
+        let mut pairs = PairWriter { initial: true, write: w };
+        let mut fields_written = false;
+        for key in self.keys() {
+            let name = self.name(key);
+            match key {
+                Key::Message => pairs.write_pair(name, &record.message.unescape())?,
+                Key::Level => pairs.write_pair(name, record.level.name())?,
+                Key::Source => if let Some(source) = &record.source {
+                    pairs.write_pair(name, &format!("{}:{}", source.file, source.line))?;
+                },
+                Key::Time => if !record.time.is_unknown() {
+                    pairs.write_pair(name, record.time.time_str)?;
+                },
+                Key::Fields | Key::Field(_) if fields_written => {}
+                Key::Fields | Key::Field(_) => {
+                    fields_written = true;
+                    self.write_fields(&mut pairs, &record.entries)?;
+                }
+                Key::Instance => if let Some(instance) = origin.instance {
+                    pairs.write_pair(name, instance)?;
+                },
+                Key::File => if let Some(file) = origin.file {
+                    pairs.write_pair(name, file)?;
+                },
+                Key::Line => if let Some(line) = origin.line {
+                    pairs.write_pair(name, &line.to_string())?;
+                },
+            }
+        }
+        Ok(())
+    }
+
+    fn write_fields<W: Write>(&self, pairs: &mut PairWriter<W>, fields: &[LogFieldRef<'_>]) -> io::Result<()> {
+        let reserved = self.keys().iter()
+            .filter(|key| !matches!(key, Key::Fields | Key::Field(_)))
+            .map(|key| self.name(key))
+            .collect::<Vec<_>>();
+        for field in self.projection.fields(fields) {
+            if reserved.contains(&field.name.as_ref()) {
+                pairs.write_key(&format!("fields.{}", field.name))?;
+            } else {
+                pairs.write_key(&field.name)?;
+            }
+            match field.values {
+                FieldValues::One(value) => self.write_value(value, &mut pairs.write)?,
+                FieldValues::Many(values) => {
+                    let joined = values.iter().map(|value| value.unescape()).collect::<Vec<_>>().join(",");
+                    write_logfmt_value(&mut pairs.write, &joined)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn write_value<W: Write>(&self, value: &LogStr<'_>, w: W) -> io::Result<()> {
+        let text = value.unescape();
+        match TypedValue::infer(&text) {
+            Some(typed) if self.typed_values != TypedValues::Off => typed.write_json_to(w),
+            _ => write_logfmt_value(w, &text),
+        }
+    }
+}
+
+impl WriteRecord for LogfmtFormat {
+    fn write_record(&mut self, record: &LogRecordRef<'_>, origin: Origin<'_>, w: &mut dyn Write) -> io::Result<()> {
+        self.write_record_from(record, origin, &mut *w)?;
+        writeln!(w)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{json_writer::TypedValues, output::Origin, parser::artifacts::{LogRecordRef, TimeRef, with_log_record}, projection::{Key, Projection}};
+
+    use super::{LogfmtFormat, write_logfmt_key, write_logfmt_value};
+
+    fn write(format: LogfmtFormat, line: &str) -> String {
+        with_log_record(line, |r| {
+            let mut buf = Vec::new();
+            let origin = Origin { file: Some("tidb.log"), ..Origin::default() };
+            format.write_record_from(&r, origin, &mut buf).unwrap();
+            String::from_utf8(buf).unwrap()
+        }).unwrap()
+    }
+
+    #[test]
+    fn test_logfmt_str() {
+        fn check(from: &str, value: &str, key: &str) {
+            let (mut v, mut k) = (Vec::new(), Vec::new());
+            write_logfmt_value(&mut v, from).unwrap();
+            write_logfmt_key(&mut k, from).unwrap();
+            assert_eq!(String::from_utf8(v).unwrap(), value, "failed to encode value {:?}", from);
+            assert_eq!(String::from_utf8(k).unwrap(), key, "failed to encode key {:?}", from);
+        }
+
+        check("", r#""""#, "");
+        check("1.345s", "1.345s", "1.345s");
+        check("192.168.0.123:12345", "192.168.0.123:12345", "192.168.0.123:12345");
+        check("TiKV Started", r#""TiKV Started""#, "TiKV_Started");
+        check("a=b", r#""a=b""#, "a_b");
+        check(r#"ID="abc""#, r#""ID=\"abc\"""#, "ID__abc_");
+        check("a\nb", r#""a\nb""#, "a_b");
+    }
+
+    #[test]
+    fn test_logfmt() {
+        let line = r#"[2018/12/15 14:20:11.015 +08:00] [WARN] [session.go:1234] ["Slow query"] [sql="SELECT * FROM TABLE\nWHERE ID=\"abc\""] [duration=1.345s] [msg=x] [txn_id=123000102231]"#;
+        assert_eq!(write(LogfmtFormat::default(), line), concat!(
+            r#"time="2018/12/15 14:20:11.015 +08:00" level=warn msg="Slow query" source=session.go:1234 "#,
+            r#"sql="SELECT * FROM TABLE\nWHERE ID=\"abc\"" duration=1.345s fields.msg=x txn_id=123000102231 _file=tidb.log"#,
+        ));
+        assert_eq!(write(LogfmtFormat::default().typed_values(TypedValues::Number), line), concat!(
+            r#"time="2018/12/15 14:20:11.015 +08:00" level=warn msg="Slow query" source=session.go:1234 "#,
+            r#"sql="SELECT * FROM TABLE\nWHERE ID=\"abc\"" duration=1.345 fields.msg=x txn_id=123000102231 _file=tidb.log"#,
+        ));
+
+        let projection = Projection::default()
+            .select(vec![Key::Level, Key::Message, Key::Field("duration".to_owned())])
+            .rename(Key::Message, "message")
+            .rename(Key::Field("duration".to_owned()), "took");
+        assert_eq!(write(LogfmtFormat::default().projection(projection), line), r#"level=warn message="Slow query" took=1.345s"#);
+        // the unknown level is spelled as the other outputs do.
+        assert_eq!(write(LogfmtFormat::default(), "[2018/12/15 14:20:11.015 +08:00] [UNKNOWN] [<unknown>] [x]"), r#"time="2018/12/15 14:20:11.015 +08:00" level=<unknown> msg=x _file=tidb.log"#);
+
+        // the unknown time is left out.
+        let untimed = with_log_record(line, |r| {
+            let mut buf = Vec::new();
+            LogfmtFormat::default().write_record_from(&LogRecordRef { time: TimeRef::UNKNOWN, ..r }, Origin::default(), &mut buf).unwrap();
+            String::from_utf8(buf).unwrap()
+        }).unwrap();
+        assert!(untimed.starts_with("level=warn "), "{}", untimed);
+    }
+}
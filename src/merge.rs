@@ -0,0 +1,145 @@
+use std::{cmp::Reverse, collections::BinaryHeap};
+
+use crate::parser::owned::LogRecord;
+
+/// Timed is an item which can be merged by its time.
+pub trait Timed {
+    fn epoch_nanos(&self) -> i128;
+}
+
+impl Timed for LogRecord {
+    fn epoch_nanos(&self) -> i128 {
+        self.time.epoch_nanos()
+    }
+}
+
+/// the numbered items, like the records with their line numbers.
+impl<T: Timed> Timed for (usize, T) {
+    fn epoch_nanos(&self) -> i128 {
+        self.1.epoch_nanos()
+    }
+}
+
+/// Merge merges the sources, each sorted by time, into one stream sorted by time.
+/// Only the head item of each source is held, so the memory is bounded by the number of sources.
+/// Items with the same time are ordered by the index of their sources.
+/// Errors are yielded as soon as they are met, since they cannot be ordered. A source stops at its error
+/// till the error is yielded, so at most one error of each source is held too.
+pub struct Merge<I, T, E> {
+    sources: Vec<I>,
+    heads: Vec<Option<T>>,
+    heap: BinaryHeap<Reverse<(i128, usize)>>,
+    /// the error met by each source, which is yielded before any item.
+    errors: Vec<Option<E>>,
+    initialized: bool,
+}
+
+impl<I: Iterator<Item = Result<T, E>>, T: Timed, E> Merge<I, T, E> {
+    pub fn new(sources: Vec<I>) -> Self {
+        Self {
+            heads: sources.iter().map(|_| None).collect(),
+            heap: BinaryHeap::with_capacity(sources.len()),
+            errors: sources.iter().map(|_| None).collect(),
+            initialized: false,
+            sources,
+        }
+    }
+
+    /// pull the next item of the source into the heap, or hold the error if it fails.
+    fn pull(&mut self, index: usize) {
+        match self.sources[index].next() {
+            Some(Ok(item)) => {
+                self.heap.push(Reverse((item.epoch_nanos(), index)));
+                self.heads[index] = Some(item);
+            }
+            Some(Err(err)) => self.errors[index] = Some(err),
+            None => {}
+        }
+    }
+}
+
+impl<I: Iterator<Item = Result<T, E>>, T: Timed, E> Iterator for Merge<I, T, E> {
+    /// the item with the index of its source.
+    type Item = (usize, Result<T, E>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if !self.initialized {
+            self.initialized = true;
+            for index in 0..self.sources.len() {
+                self.pull(index);
+            }
+        }
+        if let Some((index, err)) = self.errors.iter_mut().enumerate().find_map(|(index, err)| Some((index, err.take()?))) {
+            self.pull(index);
+            return Some((index, Err(err)));
+        }
+        let Reverse((_, index)) = self.heap.pop()?;
+        let item = self.heads[index].take().expect("the head of a source in the heap must exist");
+        self.pull(index);
+        Some((index, Ok(item)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Merge, Timed};
+
+    impl Timed for i64 {
+        fn epoch_nanos(&self) -> i128 {
+            *self as i128
+        }
+    }
+
+    #[test]
+    fn test_merge() {
+        let sources = vec![
+            vec![Ok(1), Ok(4), Err("a"), Ok(7)],
+            vec![Err("b"), Ok(2), Ok(4), Ok(5)],
+            vec![],
+            vec![Ok(3), Ok(9)],
+        ];
+        let merged = Merge::new(sources.into_iter().map(|s: Vec<Result<i64, &str>>| s.into_iter()).collect()).collect::<Vec<_>>();
+        assert_eq!(merged, vec![
+            (1, Err("b")),
+            (0, Ok(1)),
+            (1, Ok(2)),
+            (3, Ok(3)),
+            (0, Ok(4)),
+            (0, Err("a")),
+            (1, Ok(4)),
+            (1, Ok(5)),
+            (0, Ok(7)),
+            (3, Ok(9)),
+        ]);
+    }
+
+    #[test]
+    fn test_endless_errors() {
+        // a source failing forever, like a binary file, must not be drained before anything is yielded.
+        let sources: Vec<Box<dyn Iterator<Item = Result<i64, &str>>>> = vec![
+            Box::new(vec![Ok(1), Ok(2)].into_iter()),
+            Box::new(std::iter::repeat(Err("bad"))),
+        ];
+        let merged = Merge::new(sources).take(4).collect::<Vec<_>>();
+        assert_eq!(merged, vec![(1, Err("bad")), (1, Err("bad")), (1, Err("bad")), (1, Err("bad"))]);
+    }
+
+    #[test]
+    fn test_merge_records() {
+        use crate::parser::{owned::LogRecord, reader::{LogReader, ReadError}};
+        use std::io::Cursor;
+
+        let tikv = "[2021/03/09 12:15:13.000 +08:00] [INFO] [a.rs:1] [\"tikv 1\"]\n[2021/03/09 12:15:15.000 +08:00] [INFO] [a.rs:1] [\"tikv 2\"]\n";
+        let pd = "[2021/03/09 04:15:14.000 +00:00] [INFO] [a.go:1] [\"pd 1\"]\n[2021/03/09 13:15:16.000 +09:00] [INFO] [a.go:1] [\"pd 2\"]\n";
+        let sources = vec![LogReader::new(Cursor::new(tikv)), LogReader::new(Cursor::new(pd))];
+        let messages = Merge::new(sources)
+            .map(|(index, record): (usize, Result<LogRecord, ReadError>)| (index, record.unwrap().message.borrowed().unescape().into_owned()))
+            .collect::<Vec<_>>();
+        assert_eq!(messages, vec![
+            (0, "tikv 1".to_owned()),
+            (1, "pd 1".to_owned()),
+            (0, "tikv 2".to_owned()),
+            (1, "pd 2".to_owned()),
+        ]);
+    }
+}
@@ -0,0 +1,22 @@
+use std::io::{self, Write};
+
+use crate::parser::artifacts::LogRecordRef;
+
+/// Origin is where a record comes from, written as the `_instance`, `_file` and `_line` attributes if present.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Origin<'a> {
+    /// the instance the record comes from, when records of many instances are merged.
+    pub instance: Option<&'a str>,
+    pub file: Option<&'a str>,
+    pub line: Option<usize>,
+}
+
+/// WriteRecord writes records in an output format, one record per line.
+pub trait WriteRecord {
+    fn write_record(&mut self, record: &LogRecordRef<'_>, origin: Origin<'_>, w: &mut dyn Write) -> io::Result<()>;
+
+    /// write what is still buffered, after the last record.
+    fn finish(&mut self, _w: &mut dyn Write) -> io::Result<()> {
+        Ok(())
+    }
+}
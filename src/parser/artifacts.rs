@@ -1,29 +1,86 @@
-use std::{str::FromStr};
+use std::{borrow::Cow, str::FromStr};
 
 use tinyvec::{TinyVec};
 
-use super::{ParseError, scanner::Scanner};
+use super::{Component, ParseError, diagnostics::{Diagnostics, SkippedField, Stderr}, scanner::Scanner, time::Timestamp};
 
-#[derive(Debug)]
+/// LogLevel is the level of a record, ordered by severity.
+/// Unknown levels (like `TRACE`) are the least severe.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum LogLevel {
+    Unknown,
     Debug,
     Info,
     Warn,
     Error,
     Fatal,
-    Unknown
 }
 
-#[derive(Debug, PartialEq, Eq)]
+impl LogLevel {
+    /// the name of the level written by the outputs, like `warn`, or `<unknown>` for the unknown level.
+    pub fn name(self) -> &'static str {
+        match self {
+            LogLevel::Debug => "debug",
+            LogLevel::Info => "info",
+            LogLevel::Warn => "warn",
+            LogLevel::Error => "error",
+            LogLevel::Fatal => "fatal",
+            LogLevel::Unknown => "<unknown>",
+        }
+    }
+}
+
+/// LevelFilter is the set of levels of records to keep.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LevelFilter {
+    levels: u8,
+}
+
+impl Default for LevelFilter {
+    fn default() -> Self {
+        Self::all()
+    }
+}
+
+impl LevelFilter {
+    pub fn all() -> Self {
+        Self { levels: u8::MAX }
+    }
+
+    /// keep the records at the level or more severe.
+    pub fn at_least(level: LogLevel) -> Self {
+        Self { levels: u8::MAX << level as u8 }
+    }
+
+    /// keep the records at exactly one of the levels.
+    pub fn only(levels: impl IntoIterator<Item = LogLevel>) -> Self {
+        Self { levels: levels.into_iter().fold(0, |set, level| set | 1 << level as u8) }
+    }
+
+    /// keep the records both filters keep.
+    pub fn and(self, other: Self) -> Self {
+        Self { levels: self.levels & other.levels }
+    }
+
+    pub fn accepts(&self, level: LogLevel) -> bool {
+        self.levels & 1 << level as u8 != 0
+    }
+
+    pub fn accepts_all(&self) -> bool {
+        *self == Self::all()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct LogFieldRef<'a> {
     pub key: LogStr<'a>,
     pub value: LogStr<'a>,
 }
 
-const TINY_VEC_THRESHOLD : usize = 12;
+pub(super) const TINY_VEC_THRESHOLD : usize = 12;
 
 /// LogRecordRef is a line of PingCAP log.
-#[derive(Debug)]
+#[derive(Debug, PartialEq, Eq)]
 pub struct LogRecordRef<'a> {
     pub level: LogLevel,
     pub time: TimeRef<'a>,
@@ -32,32 +89,151 @@ pub struct LogRecordRef<'a> {
     pub entries: TinyVec<[LogFieldRef<'a>; TINY_VEC_THRESHOLD]>
 }
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq, Eq)]
 pub struct FileLineRef<'a> {
-    pub file: &'a str,
-    pub line: &'a str,
+    pub file: Cow<'a, str>,
+    pub line: Cow<'a, str>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq, Eq)]
 pub struct TimeRef<'a> {
     pub time_str: &'a str,
+    pub timestamp: Timestamp,
 }
 
 impl<'a> TimeRef<'a> {
-    fn from_str_unchecked(s: &'a str) -> Self {
-        Self {
-            time_str: s
+    /// the time of a record which doesn't have any, like a plain text line before any record with time.
+    /// Its string is empty, and the outputs write it as absent where they can.
+    pub const UNKNOWN: TimeRef<'static> = TimeRef { time_str: "", timestamp: Timestamp::EPOCH };
+
+    pub fn is_unknown(&self) -> bool {
+        self.time_str.is_empty()
+    }
+
+    /// the nanoseconds since the unix epoch, which is comparable between different timezones.
+    pub fn epoch_nanos(&self) -> i128 {
+        self.timestamp.epoch_nanos()
+    }
+
+    /// parse the `[timestamp]` at the beginning of a record, the rest of the record is ignored.
+    pub fn parse(s: &'a str) -> Result<Self, ParseError> {
+        Self::scan_from(&Scanner::over(s))
+    }
+
+    fn scan_from(text: &Scanner<'a>) -> Result<Self, ParseError> {
+        text.consume_exact('[')?;
+        let start = text.offset();
+        let timestamp = Timestamp::scan_from(text)?;
+        let time_str = text.since(start);
+        match text.peek_char() {
+            Some(']') => text.consume_exact(']')?,
+            Some(ch) => return Err(text.unexpected("']' after timestamp", ch)),
+            None => return Err(text.unexpected("']' after timestamp", "EOF")),
         }
+        Ok(Self { time_str, timestamp })
     }
 }
 
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Debug, Clone, Eq, PartialEq)]
 pub enum LogStr<'a> {
     Quoted(&'a str),
     Unquoted(&'a str)
 }
 
 impl <'a> LogStr<'a> {
+    /// decode the string as the unified log format RFC describes.
+    /// Unquoted strings and quoted strings without any escape are borrowed as-is.
+    ///
+    /// Besides the escapes of JSON, the `\xHH`, `\u{H..}`, `\UHHHHHHHH`, `\a`, `\v` and `\0` escapes
+    /// (which are emitted by the Go and Rust loggers) are decoded, `\xHH` escapes that don't form
+    /// valid UTF-8 are replaced with `U+FFFD`, and unknown escapes are kept verbatim.
+    pub fn unescape(&self) -> Cow<'a, str> {
+        let s = match self {
+            Self::Unquoted(s) => return Cow::Borrowed(s),
+            Self::Quoted(s) => s.strip_prefix('"').map(|s| s.strip_suffix('"').unwrap_or(s)).unwrap_or(s),
+        };
+        if !s.contains('\\') {
+            return Cow::Borrowed(s);
+        }
+
+        let mut buf = Vec::with_capacity(s.len());
+        let mut rest = s;
+        while let Some(i) = rest.find('\\') {
+            buf.extend_from_slice(&rest.as_bytes()[..i]);
+            rest = &rest[i+1..];
+            let mut chars = rest.chars();
+            let escaped = match chars.next() {
+                Some(ch) => ch,
+                None => {
+                    buf.push(b'\\');
+                    break;
+                }
+            };
+            rest = chars.as_str();
+            match escaped {
+                'n' => buf.push(b'\n'),
+                'r' => buf.push(b'\r'),
+                't' => buf.push(b'\t'),
+                'b' => buf.push(0x08),
+                'f' => buf.push(0x0c),
+                'v' => buf.push(0x0b),
+                'a' => buf.push(0x07),
+                '0' => buf.push(0x00),
+                '"' | '\\' | '/' | '\'' => buf.push(escaped as u8),
+                'x' => match hex_prefix(rest, 2) {
+                    Some(byte) => {
+                        buf.push(byte as u8);
+                        rest = &rest[2..];
+                    }
+                    None => buf.extend_from_slice(b"\\x"),
+                },
+                'u' if rest.starts_with('{') => match rest.find('}').and_then(|end| Some((end, hex_prefix(&rest[1..end], end - 1)?))) {
+                    Some((end, code)) => {
+                        push_code_point(&mut buf, code);
+                        rest = &rest[end+1..];
+                    }
+                    None => buf.extend_from_slice(b"\\u"),
+                },
+                'u' => match hex_prefix(rest, 4) {
+                    Some(high @ 0xd800..=0xdbff) => {
+                        rest = &rest[4..];
+                        let low = rest.strip_prefix("\\u").and_then(|r| hex_prefix(r, 4));
+                        match low {
+                            Some(low @ 0xdc00..=0xdfff) => {
+                                push_code_point(&mut buf, 0x10000 + ((high - 0xd800) << 10) + (low - 0xdc00));
+                                rest = &rest[6..];
+                            }
+                            _ => push_code_point(&mut buf, high),
+                        }
+                    }
+                    Some(code) => {
+                        push_code_point(&mut buf, code);
+                        rest = &rest[4..];
+                    }
+                    None => buf.extend_from_slice(b"\\u"),
+                },
+                'U' => match hex_prefix(rest, 8) {
+                    Some(code) => {
+                        push_code_point(&mut buf, code);
+                        rest = &rest[8..];
+                    }
+                    None => buf.extend_from_slice(b"\\U"),
+                },
+                other => {
+                    buf.push(b'\\');
+                    let mut utf8 = [0u8; 4];
+                    buf.extend_from_slice(other.encode_utf8(&mut utf8).as_bytes());
+                }
+            }
+        }
+        buf.extend_from_slice(rest.as_bytes());
+
+        match String::from_utf8(buf) {
+            Ok(s) => Cow::Owned(s),
+            Err(err) => Cow::Owned(String::from_utf8_lossy(err.as_bytes()).into_owned()),
+        }
+    }
+
     fn from_str(s: &'a str) -> Result<Self, ParseError> {
         match s.chars().next() {
             None => Ok(Self::Unquoted("")),
@@ -71,7 +247,7 @@ impl <'a> LogStr<'a> {
         let got = match text.peek_char() {
             Some('"') => text.quoted_string(),
             Some(_) => text.unquoted_string(),
-            None => Err(super::scanner::empty()),
+            None => Err(text.unexpected("a string", "EOF")),
         }?;
         Self::from_str(got)
     }
@@ -80,40 +256,60 @@ impl <'a> LogStr<'a> {
         let got = match text.peek_char() {
             Some('"') => text.quoted_string(),
             Some(_) => text.consume_until(f),
-            None => Err(super::scanner::empty()),
+            None => Err(text.unexpected("a string", "EOF")),
         }?;
         Self::from_str(got)
     }
 }
 
+/// parse the leading `n` hex digits of `s`.
+fn hex_prefix(s: &str, n: usize) -> Option<u32> {
+    let digits = s.get(..n)?;
+    if n == 0 || !digits.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+    u32::from_str_radix(digits, 16).ok()
+}
+
+/// push the UTF-8 encoding of `code` to `buf`, invalid code points (i.e. lone surrogates) become `U+FFFD`.
+fn push_code_point(buf: &mut Vec<u8>, code: u32) {
+    let ch = char::from_u32(code).unwrap_or(char::REPLACEMENT_CHARACTER);
+    let mut utf8 = [0u8; 4];
+    buf.extend_from_slice(ch.encode_utf8(&mut utf8).as_bytes());
+}
+
 impl<'a> FileLineRef<'a> {
     const UNKNOWN : &'static str = "<unknown>";
 
-    fn from_str(s: &'a str) -> Option<Self> {
+    /// split the unescaped `file:line` at its last colon, it is borrowed unless it had escapes.
+    pub(super) fn parse(s: Cow<'a, str>) -> Option<Self> {
         if s == Self::UNKNOWN {
             return None
         }
 
-        let mut colons = Vec::with_capacity(8);
-        for (i, ch) in s.char_indices() {
-            if ch == ':' {
-                colons.push(i);
+        let colon = s.rfind(':')?;
+        Some(match s {
+            Cow::Borrowed(s) => Self { file: Cow::Borrowed(&s[..colon]), line: Cow::Borrowed(&s[colon + 1..]) },
+            Cow::Owned(mut file) => {
+                let line = file.split_off(colon + 1);
+                file.truncate(colon);
+                Self { file: Cow::Owned(file), line: Cow::Owned(line) }
             }
-        }
-        colons.last().map(|i| {
-            let (file, line) = s.split_at(*i);
-            Self { file, line: &line[1..] }
         })
     }
 
-    fn scan_from<'b: 'a>(text: &'b Scanner<'a>) -> Result<Option<Self>, ParseError> {
-        let source = text.in_bracket( |s: &Scanner| { s.till_next_bracket() })?;
-        Ok(Self::from_str(source))
+    /// the source may be quoted when the file has spaces or brackets.
+    fn scan_from(text: &Scanner<'a>) -> Result<Option<Self>, ParseError> {
+        let source = text.in_bracket(|s: &Scanner| match s.peek_char() {
+            Some('"') => s.quoted_string().map(LogStr::Quoted),
+            _ => s.till_next_bracket().map(LogStr::Unquoted),
+        })?;
+        Ok(Self::parse(source.unescape()))
     }
 }
 
 impl<'a> LogFieldRef<'a> {
-    fn scan_from(text: &'a Scanner) -> Result<Self, ParseError> {
+    fn scan_from(text: &Scanner<'a>) -> Result<Self, ParseError> {
         let key = LogStr::parse_from_sequence(text)?;
         text.consume_exact('=')?;
 
@@ -131,13 +327,13 @@ impl<'a> LogFieldRef<'a> {
         Ok(Self { key, value })
     }
 
-    fn parse_from_field<'b : 'a>(text: &'b Scanner<'a>) -> Result<Self, ParseError> {
+    fn parse_from_field(text: &Scanner<'a>) -> Result<Self, ParseError> {
         text.in_bracket(Self::scan_from)
     }
 }
 
 impl LogLevel {
-    fn scan_from<'a, 'b:'a>(text: &'b Scanner<'a>) -> Result<Self, ParseError> {
+    fn scan_from(text: &Scanner) -> Result<Self, ParseError> {
         let field = text.in_bracket(|s| s.till_next_bracket())?;
         Self::from_str(field)
     }
@@ -169,25 +365,85 @@ impl<'a> Default for LogFieldRef<'a> {
     }
 }
 
-impl<'a> LogRecordRef<'a> {
-    fn scan_from<'b: 'a>(scanner: &'b Scanner<'a>) -> Result<Self, ParseError> {
-        let time = TimeRef::from_str_unchecked(scanner.in_bracket(|s| s.till_next_bracket())?);
+/// RecordHeader is the time and the level at the beginning of a line of the unified log format.
+/// They are parsed before the rest of the line, so records can be dropped by their levels
+/// before the rest is read or parsed, see `LogRecordRef::parse_rest`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RecordHeader {
+    pub timestamp: Timestamp,
+    pub level: LogLevel,
+    /// the length of the time string.
+    time_len: usize,
+    /// the offset of the rest of the line.
+    rest: usize,
+}
+
+impl RecordHeader {
+    /// parse the time and the level of a line of the unified log format, the rest of the line is ignored.
+    pub fn parse(s: &str) -> Result<Self, ParseError> {
+        let scanner = Scanner::over(s);
+        scanner.parsing(Component::Time);
+        let time = TimeRef::scan_from(&scanner)?;
         scanner.skip_space();
-        let level= LogLevel::scan_from(&scanner)?;
+        scanner.parsing(Component::Level);
+        let level = LogLevel::scan_from(&scanner)?;
+        Ok(Self { timestamp: time.timestamp, level, time_len: time.time_str.len(), rest: scanner.offset() })
+    }
+}
+
+impl<'a> LogRecordRef<'a> {
+    /// parse a line of the unified log format, the record borrows the line.
+    /// Fields failed to parse are skipped and printed to stderr, use `parse_with` to handle them.
+    pub fn parse(s: &'a str) -> Result<Self, ParseError> {
+        Self::parse_with(s, Stderr)
+    }
+
+    /// parse a line of the unified log format, fields failed to parse are skipped and sent to `diagnostics`.
+    pub fn parse_with(s: &'a str, diagnostics: impl Diagnostics) -> Result<Self, ParseError> {
+        let record = Self::parse_filtered(s, diagnostics, LevelFilter::all())?;
+        Ok(record.expect("records of all levels are kept"))
+    }
+
+    /// parse a line of the unified log format if its level is kept by the filter, otherwise
+    /// `None` is returned without parsing the rest of the line.
+    pub fn parse_filtered(s: &'a str, diagnostics: impl Diagnostics, filter: LevelFilter) -> Result<Option<Self>, ParseError> {
+        let header = RecordHeader::parse(s)?;
+        if !filter.accepts(header.level) {
+            return Ok(None);
+        }
+        Self::parse_rest(s, header, diagnostics).map(Some)
+    }
+
+    /// parse the rest of a line of the unified log format after its header, which is parsed from the same line.
+    pub fn parse_rest(s: &'a str, header: RecordHeader, mut diagnostics: impl Diagnostics) -> Result<Self, ParseError> {
+        let scanner = Scanner::over(s);
+        scanner.consume(header.rest)?;
+        // the time is right after the leading `[`.
+        let time = TimeRef { time_str: &s[1..1 + header.time_len], timestamp: header.timestamp };
+        Self::scan_from(&scanner, time, header.level, &mut diagnostics)
+    }
+
+    fn scan_from(scanner: &Scanner<'a>, time: TimeRef<'a>, level: LogLevel, diagnostics: &mut dyn Diagnostics) -> Result<Self, ParseError> {
         scanner.skip_space();
-        let source = FileLineRef::scan_from(&scanner)?;
+        scanner.parsing(Component::Source);
+        let source = FileLineRef::scan_from(scanner)?;
         scanner.skip_space();
+        scanner.parsing(Component::Message);
         let message = scanner.in_bracket(LogStr::parse_from_sequence)?;
         scanner.skip_space();
 
         let mut entries = TinyVec::<[LogFieldRef; TINY_VEC_THRESHOLD]>::default();
+        let mut n = 0;
         while !scanner.is_done() {
-            let field = match LogFieldRef::parse_from_field(& scanner) {
-                Err(err) => {
-                    // TODO use slog!
-                    eprintln!("meet error {} during parsing, skipping this field", err);
+            scanner.parsing(Component::Field(n));
+            n += 1;
+            let start = scanner.offset();
+            let field = match LogFieldRef::parse_from_field(scanner) {
+                Err(error) => {
                     scanner.skip_until(|c| c == ']');
                     scanner.consume_exact(']')?;
+                    diagnostics.on_skipped_field(SkippedField { error, text: scanner.since(start), source: scanner.target() });
+                    scanner.skip_space();
                     continue;
                 }
                 Ok(pair) => pair
@@ -200,13 +456,26 @@ impl<'a> LogRecordRef<'a> {
     }
 }
 
+/// check whether the line looks like starting with a `[timestamp]` header, i.e. it is the first line of a record.
+/// Only the leading `[YYYY/` is checked, so a malformed header still fails on parsing instead of
+/// being taken as a continuation line, while a continuation line like `[1] 12345 Segmentation fault` is not a header.
+pub fn is_record_header(line: &str) -> bool {
+    match line.as_bytes().get(..6) {
+        Some([b'[', year @ .., b'/']) => year.iter().all(u8::is_ascii_digit),
+        _ => false,
+    }
+}
+
 pub fn with_log_record<'a, T: 'a>(s: &'a str, callback: impl FnOnce(LogRecordRef<'_>) -> T) -> Result<T, ParseError> {
-    let scanner = Scanner::over(s);
-    Ok(callback(LogRecordRef::scan_from(&scanner)?))
+    Ok(callback(LogRecordRef::parse(s)?))
 }
 
 pub fn with_zap_object<'a, T: 'a>(s: &'a str, callback: impl FnOnce(&[LogFieldRef<'_>]) -> T) -> Result<T, ParseError> {
-    let scanner = Scanner::over(s);
+    Ok(callback(&scan_zap_object(&Scanner::over(s))?))
+}
+
+/// scan the fields of a zap object like `{a=1,b="x y"}`.
+pub(super) fn scan_zap_object<'a>(scanner: &Scanner<'a>) -> Result<TinyVec<[LogFieldRef<'a>; TINY_VEC_THRESHOLD]>, ParseError> {
     let mut first = true;
     let mut vec = TinyVec::<[_; TINY_VEC_THRESHOLD]>::new();
     scanner.consume_exact('{')?;
@@ -217,22 +486,64 @@ pub fn with_zap_object<'a, T: 'a>(s: &'a str, callback: impl FnOnce(&[LogFieldRe
                 Some(',') => scanner.consume_exact(',')?,
                 Some('}') => {
                     scanner.consume_exact('}')?;
-                    return Ok(callback(&vec))
+                    return Ok(vec)
                 }
                 Some(any) => {
                     return Err(scanner.unexpected("',' or '}'", any))
                 }
-                None => return Err(ParseError::Empty),
+                None => return Err(scanner.unexpected("',' or '}'", "EOF")),
             }
         }
         first = false;
-        let field = LogFieldRef::scan_from_with_need_quote(&scanner, |c| {
+        scanner.parsing(Component::Field(vec.len()));
+        let field = LogFieldRef::scan_from_with_need_quote(scanner, |c| {
             super::scanner::char_need_quote(c) || c == ',' || c == '}' || c == '{'
         })?;
         vec.push(field);
     }
 }
 
+impl<'a> LogRecordRef<'a> {
+    /// parse a zap object as a record, like `parse_json`: the `time` field is required, the `level`, `caller`
+    /// and `msg` fields are optional, and the rest fields become the fields of the record.
+    pub fn parse_zap_object(s: &'a str) -> Result<Self, ParseError> {
+        let scanner = Scanner::over(s);
+        let fields = scan_zap_object(&scanner)?;
+        let mut time = None;
+        let mut level = LogLevel::Unknown;
+        let mut source = None;
+        let mut message = LogStr::Unquoted("");
+        let mut entries = TinyVec::<[LogFieldRef; TINY_VEC_THRESHOLD]>::default();
+        for field in fields {
+            match field.key.unescape().as_ref() {
+                "time" => {
+                    // the timestamp has no escape, so it is scanned in place for the location of errors.
+                    let text = match field.value {
+                        LogStr::Quoted(s) => &s[1..s.len() - 1],
+                        LogStr::Unquoted(s) => s,
+                    };
+                    let start = text.as_ptr() as usize - s.as_ptr() as usize;
+                    let scanner = Scanner::over(s);
+                    scanner.consume(start)?;
+                    scanner.parsing(Component::Time);
+                    let timestamp = Timestamp::scan_from(&scanner)?;
+                    if scanner.offset() != start + text.len() {
+                        return Err(scanner.unexpected("the end of the timestamp", scanner.current_char()));
+                    }
+                    time = Some(TimeRef { time_str: text, timestamp });
+                }
+                "level" => level = field.value.unescape().to_ascii_uppercase().parse()?,
+                "caller" => source = FileLineRef::parse(field.value.unescape()),
+                "msg" => message = field.value,
+                _ => entries.push(field),
+            }
+        }
+        scanner.parsing(Component::Time);
+        let time = time.ok_or_else(|| scanner.unexpected("the \"time\" field", "EOF"))?;
+        Ok(Self { level, time, source, message, entries })
+    }
+}
+
 mod displaying {
     use std::fmt::{self, Display};
 
@@ -256,6 +567,29 @@ mod displaying {
 mod tests {
     use crate::parser::scanner::Scanner;
 
+    #[test]
+    fn test_level_order() {
+        use super::{LevelFilter, LogLevel::*};
+
+        assert!(Unknown < Debug && Debug < Info && Info < Warn && Warn < Error && Error < Fatal);
+        let at_least_warn = LevelFilter::at_least(Warn);
+        assert_eq!([Unknown, Debug, Info, Warn, Error, Fatal].map(|l| at_least_warn.accepts(l)), [false, false, false, true, true, true]);
+        assert!(LevelFilter::at_least(Unknown).accepts_all());
+        assert!(!LevelFilter::only([Error]).accepts(Fatal));
+    }
+
+    #[test]
+    fn test_record_header() {
+        use super::is_record_header;
+
+        assert!(is_record_header("[2018/12/15 14:20:11.015 +08:00] [INFO] [a.rs:1] [x]"));
+        assert!(is_record_header("[2018/12/15 14:20:11] malformed"));
+        assert!(!is_record_header("[1] 12345 Segmentation fault"));
+        assert!(!is_record_header("[2018-12-15 14:20:11]"));
+        assert!(!is_record_header("[2018"));
+        assert!(!is_record_header("  [2018/12/15 14:20:11.015 +08:00]"));
+    }
+
     #[test]
     fn test_log_str() {
         use super::LogStr;
@@ -285,6 +619,29 @@ mod tests {
         check_scan(r#""rate limit"=128MB/s"#, "\"rate limit\"", "=128MB/s");
     }
 
+    #[test]
+    fn test_log_str_unescape() {
+        use super::LogStr;
+
+        fn check(from: &str, to: &str) {
+            let scanner = Scanner::over(from);
+            let log_str = LogStr::parse_from_sequence(&scanner).unwrap();
+            assert_eq!(log_str.unescape(), to, "failed to unescape {}", from);
+        }
+
+        check("128MB/s", "128MB/s");
+        check(r#""hello, world""#, "hello, world");
+        check(r#""SELECT * FROM TABLE\nWHERE ID=\"abc\"""#, "SELECT * FROM TABLE\nWHERE ID=\"abc\"");
+        check(r#""C:\\tikv\tdata""#, "C:\\tikv\tdata");
+        check(r#""\x1b[31merror\x1b[0m""#, "\u{1b}[31merror\u{1b}[0m");
+        check(r#""\u001b[0m \u{1b}[0m \U0001F600""#, "\u{1b}[0m \u{1b}[0m \u{1F600}");
+        check(r#""\ud83d\ude08 \ud83d""#, "😈 \u{FFFD}");
+        check(r#""\xe4\xbd\xa0\xe5\xa5\xbd \xff""#, "你好 \u{FFFD}");
+        check(r#""\a\v\0\/\'""#, "\u{7}\u{b}\u{0}/'");
+        check(r#""unknown \q, bad \xZZ \u12""#, "unknown \\q, bad \\xZZ \\u12");
+        check(r#""😊👼🇺🇳╮(￣▽￣\"\")╭""#, r#"😊👼🇺🇳╮(￣▽￣"")╭"#);
+    }
+
     #[test]
     fn test_log_entry() {
         use super::LogFieldRef;
@@ -306,4 +663,20 @@ mod tests {
         let entry = r#""rate l\n\"imit"="128 MB/s""#.to_owned();
         check(&entry, r#""rate l\n\"imit""#, r#""128 MB/s""#);
     }
+
+    #[test]
+    fn test_parse_zap_object() {
+        use super::{FileLineRef, LogLevel, LogRecordRef};
+
+        let record = LogRecordRef::parse_zap_object(r#"{time="2018/12/15 14:20:11.015 +08:00",level=WARN,caller=session.go:1234,msg="Slow query",conn=3}"#).unwrap();
+        assert_eq!((record.time.time_str, record.level), ("2018/12/15 14:20:11.015 +08:00", LogLevel::Warn));
+        assert_eq!(record.source, Some(FileLineRef { file: "session.go".into(), line: "1234".into() }));
+        assert_eq!(record.message.unescape(), "Slow query");
+        assert_eq!(record.entries.iter().map(|f| format!("{}={}", f.key.unescape(), f.value.unescape())).collect::<Vec<_>>(), vec!["conn=3"]);
+
+        let err = LogRecordRef::parse_zap_object("{conn=3}").unwrap_err();
+        assert!(err.to_string().contains("\"time\" field"), "{}", err);
+        let err = LogRecordRef::parse_zap_object(r#"{conn=3,time="2018/12/15 14:20:11 +08:00"}"#).unwrap_err();
+        assert!(matches!(err, super::ParseError::Unexpected { offset: 33, .. }), "{:?}", err);
+    }
 }
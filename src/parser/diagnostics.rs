@@ -0,0 +1,101 @@
+use super::ParseError;
+
+/// SkippedField is a field failed to parse, which has been skipped so the rest of the record can be parsed.
+#[derive(Debug)]
+pub struct SkippedField<'a> {
+    /// the error, which knows the offset and the index of the field.
+    pub error: ParseError,
+    /// the text of the skipped field, brackets included.
+    pub text: &'a str,
+    /// the input the record is parsed from.
+    pub source: &'a str,
+}
+
+/// Diagnostics receives the recoverable errors during parsing,
+/// so they can be captured, counted or suppressed by the caller.
+pub trait Diagnostics {
+    fn on_skipped_field(&mut self, field: SkippedField<'_>);
+}
+
+/// Stderr prints a line to stderr for each skipped field.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Stderr;
+
+/// Ignore drops all diagnostics.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Ignore;
+
+impl Diagnostics for Stderr {
+    fn on_skipped_field(&mut self, field: SkippedField<'_>) {
+        eprintln!("meet error {} during parsing, skipping this field", field.error);
+    }
+}
+
+impl Diagnostics for Ignore {
+    fn on_skipped_field(&mut self, _field: SkippedField<'_>) {}
+}
+
+impl<F: FnMut(SkippedField<'_>)> Diagnostics for F {
+    fn on_skipped_field(&mut self, field: SkippedField<'_>) {
+        self(field)
+    }
+}
+
+/// WithLine attaches the line number to the diagnostics before passing them to the inner one.
+pub(super) struct WithLine<'d, D: ?Sized> {
+    pub inner: &'d mut D,
+    pub line: usize,
+}
+
+impl<'d, D: Diagnostics + ?Sized> Diagnostics for WithLine<'d, D> {
+    fn on_skipped_field(&mut self, mut field: SkippedField<'_>) {
+        field.error = field.error.with_line(self.line);
+        self.inner.on_skipped_field(field)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use crate::parser::{Component, ParseError, artifacts::LogRecordRef, reader::LogReader};
+
+    use super::{Ignore, SkippedField};
+
+    const LINE: &str = r#"[2018/12/15 14:20:11.015 +08:00] [INFO] [a.rs:1] ["x"] [a=1] [b="2] [c] [d=4]"#;
+
+    #[test]
+    fn test_capture_skipped_fields() {
+        let mut skipped = Vec::new();
+        let record = LogRecordRef::parse_with(LINE, |f: SkippedField<'_>| {
+            assert_eq!(f.source, LINE);
+            skipped.push((f.text.to_owned(), f.error));
+        }).unwrap();
+        let keys = record.entries.iter().map(|f| f.key.unescape()).collect::<Vec<_>>();
+        assert_eq!(keys, vec!["a", "d"]);
+
+        assert_eq!(skipped.len(), 2);
+        assert_eq!(skipped[0].0, r#"[b="2]"#);
+        assert!(matches!(skipped[0].1, ParseError::Unexpected { component: Component::Field(1), offset: 64, line: None, .. }), "{:?}", skipped[0].1);
+        assert_eq!(skipped[1].0, "[c]");
+        assert!(matches!(skipped[1].1, ParseError::Unexpected { component: Component::Field(2), .. }), "{:?}", skipped[1].1);
+    }
+
+    #[test]
+    fn test_reader_diagnostics() {
+        let input = format!("{}\n{}\n", LINE.replace("[b=\"2] [c] ", ""), LINE);
+        let mut lines = Vec::new();
+        let mut reader = LogReader::new(Cursor::new(input)).with_diagnostics(|f: SkippedField<'_>| match f.error {
+            ParseError::Unexpected { line, .. } => lines.push(line),
+            ParseError::Empty => unreachable!(),
+        });
+        while let Some(record) = reader.next_record() {
+            assert_eq!(record.unwrap().entries.len(), 2);
+        }
+        drop(reader);
+        assert_eq!(lines, vec![Some(2), Some(2)]);
+
+        let records = LogReader::new(Cursor::new(LINE)).with_diagnostics(Ignore).collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(records[0].entries.len(), 2);
+    }
+}
@@ -0,0 +1,191 @@
+use tinyvec::TinyVec;
+
+use super::{Component, ParseError, artifacts::*, scanner::Scanner, time::Timestamp};
+
+impl<'a> LogRecordRef<'a> {
+    /// parse a JSON log line like `{"level":"WARN","time":"2018/12/15 14:20:11.015 +08:00","caller":"session.go:1234","msg":"Slow query","sql":"..."}`,
+    /// which is written by the components configured with `log.format = "json"`.
+    /// The `time` is required, the `level`, `caller` and `msg` are optional, and the rest keys become the fields.
+    /// Strings are borrowed as quoted strings, and other values (including objects and arrays) as their raw JSON text.
+    pub fn parse_json(s: &'a str) -> Result<Self, ParseError> {
+        let scanner = Scanner::over(s);
+        let mut time = None;
+        let mut level = LogLevel::Unknown;
+        let mut source = None;
+        let mut message = LogStr::Unquoted("");
+        let mut entries = TinyVec::<[LogFieldRef; TINY_VEC_THRESHOLD]>::default();
+
+        scanner.skip_space();
+        scanner.consume_exact('{')?;
+        scanner.skip_space();
+        let mut first = true;
+        loop {
+            match scanner.peek_char() {
+                Some('}') => {
+                    scanner.consume_exact('}')?;
+                    break;
+                }
+                Some(',') if !first => {
+                    scanner.consume_exact(',')?;
+                    scanner.skip_space();
+                }
+                Some(ch) if !first => return Err(scanner.unexpected("',' or '}'", ch)),
+                Some(_) => {}
+                None => return Err(scanner.unexpected("'}'", "EOF")),
+            }
+            first = false;
+
+            scanner.parsing(Component::Field(entries.len()));
+            match scanner.peek_char() {
+                Some('"') => {}
+                Some(ch) => return Err(scanner.unexpected("'\"' of a key", ch)),
+                None => return Err(scanner.unexpected("'\"' of a key", "EOF")),
+            }
+            let key = LogStr::Quoted(scanner.quoted_string()?);
+            scanner.skip_space();
+            scanner.consume_exact(':')?;
+            scanner.skip_space();
+            match key.unescape().as_ref() {
+                "time" => {
+                    scanner.parsing(Component::Time);
+                    time = Some(scan_time(&scanner)?);
+                }
+                "level" => {
+                    scanner.parsing(Component::Level);
+                    let value = scan_value(&scanner)?;
+                    level = value.unescape().to_ascii_uppercase().parse()?;
+                }
+                "caller" => {
+                    scanner.parsing(Component::Source);
+                    let value = scan_value(&scanner)?;
+                    source = match value {
+                        LogStr::Quoted(_) => FileLineRef::parse(value.unescape()),
+                        LogStr::Unquoted(_) => None,
+                    };
+                }
+                "msg" => {
+                    scanner.parsing(Component::Message);
+                    message = scan_value(&scanner)?;
+                }
+                _ => {
+                    let value = scan_value(&scanner)?;
+                    entries.push(LogFieldRef { key, value });
+                }
+            }
+            scanner.skip_space();
+        }
+        scanner.skip_space();
+        scanner.parsing(Component::Record);
+        // `skip_space` keeps the spaces at the end of the line.
+        if let Some(ch) = scanner.remain().trim_start().chars().next() {
+            return Err(scanner.unexpected("the end of the line after '}'", ch));
+        }
+
+        scanner.parsing(Component::Time);
+        let time = time.ok_or_else(|| scanner.unexpected("the \"time\" key", "EOF"))?;
+        Ok(Self { level, time, source, message, entries })
+    }
+}
+
+/// scan the quoted timestamp of the `time` key.
+pub(super) fn scan_time<'a>(scanner: &Scanner<'a>) -> Result<TimeRef<'a>, ParseError> {
+    scanner.consume_exact('"')?;
+    let start = scanner.offset();
+    let timestamp = Timestamp::scan_from(scanner)?;
+    let time_str = scanner.since(start);
+    match scanner.peek_char() {
+        Some('"') => scanner.consume_exact('"')?,
+        Some(ch) => return Err(scanner.unexpected("'\"' after timestamp", ch)),
+        None => return Err(scanner.unexpected("'\"' after timestamp", "EOF")),
+    }
+    Ok(TimeRef { time_str, timestamp })
+}
+
+/// scan a JSON value, strings are quoted and the others are their raw text.
+fn scan_value<'a>(scanner: &Scanner<'a>) -> Result<LogStr<'a>, ParseError> {
+    match scanner.peek_char() {
+        Some('"') => Ok(LogStr::Quoted(scanner.quoted_string()?)),
+        Some('{') | Some('[') => Ok(LogStr::Unquoted(scan_nested(scanner)?)),
+        Some(ch) if ch == ',' || ch == '}' || ch == ']' || ch == ':' => Err(scanner.unexpected("a value", ch)),
+        Some(_) => Ok(LogStr::Unquoted(scanner.consume_until(|c| c == ',' || c == '}' || c == ']' || c.is_whitespace())?)),
+        None => Err(scanner.unexpected("a value", "EOF")),
+    }
+}
+
+/// scan an object or an array, the brackets in strings are skipped.
+fn scan_nested<'a>(scanner: &Scanner<'a>) -> Result<&'a str, ParseError> {
+    let mut depth = 0;
+    let mut in_string = false;
+    let mut escaping = false;
+    for (i, ch) in scanner.remain().char_indices() {
+        match ch {
+            _ if escaping => escaping = false,
+            '\\' if in_string => escaping = true,
+            '"' => in_string = !in_string,
+            _ if in_string => {}
+            '{' | '[' => depth += 1,
+            '}' | ']' => {
+                depth -= 1;
+                if depth == 0 {
+                    return scanner.consume(i + 1);
+                }
+            }
+            _ => {}
+        }
+    }
+    Err(scanner.unexpected("the end of the object or array", "EOF"))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parser::{Component, ParseError, artifacts::{FileLineRef, LogLevel, LogRecordRef, LogStr}};
+
+    #[test]
+    fn test_parse_json() {
+        let line = r#"{"level":"WARN","time":"2018/12/15 14:20:11.015 +08:00","caller":"session.go:1234","msg":"Slow query","sql":"SELECT * FROM TABLE\nWHERE ID=\"abc\"","duration":1.345,"ok":true,"labels":{"zone":"z1","ids":[1, 2]},"none":null}"#;
+        let record = LogRecordRef::parse_json(line).unwrap();
+        assert_eq!(record.level, LogLevel::Warn);
+        assert_eq!(record.time.time_str, "2018/12/15 14:20:11.015 +08:00");
+        assert_eq!(record.source, Some(FileLineRef { file: "session.go".into(), line: "1234".into() }));
+        assert_eq!(record.message.unescape(), "Slow query");
+        let fields = record.entries.iter().map(|f| (f.key.unescape().into_owned(), f.value.unescape().into_owned())).collect::<Vec<_>>();
+        assert_eq!(fields, vec![
+            ("sql".to_owned(), "SELECT * FROM TABLE\nWHERE ID=\"abc\"".to_owned()),
+            ("duration".to_owned(), "1.345".to_owned()),
+            ("ok".to_owned(), "true".to_owned()),
+            ("labels".to_owned(), r#"{"zone":"z1","ids":[1, 2]}"#.to_owned()),
+            ("none".to_owned(), "null".to_owned()),
+        ]);
+
+        let record = LogRecordRef::parse_json(r#"{"time":"2018/12/15 14:20:11.015 +08:00","caller":"C:\\tidb\\session.go:1234"}"#).unwrap();
+        assert_eq!(record.source, Some(FileLineRef { file: r"C:\tidb\session.go".into(), line: "1234".into() }));
+
+        let record = LogRecordRef::parse_json(r#" { "time" : "2021/03/09 12:15:13.145 +08:00" , "level" : "info" , "msg" : "a \"}\" b" } "#).unwrap();
+        assert_eq!((record.level, record.source, record.message), (LogLevel::Info, None, LogStr::Quoted(r#""a \"}\" b""#)));
+        let record = LogRecordRef::parse_json(r#"{"time":"2021/03/09 12:15:13.145 +08:00"}"#).unwrap();
+        assert_eq!((record.level, record.message, record.entries.len()), (LogLevel::Unknown, LogStr::Unquoted(""), 0));
+    }
+
+    #[test]
+    fn test_malformed_json() {
+        fn check(line: &str, component: Component, message: &str) {
+            match LogRecordRef::parse_json(line) {
+                Err(err @ ParseError::Unexpected { .. }) => {
+                    assert!(err.to_string().contains(message), "{:?}: {} should contain {:?}", line, err, message);
+                    if let ParseError::Unexpected { component: c, .. } = err {
+                        assert_eq!(c, component, "{:?}", line);
+                    }
+                }
+                other => panic!("{:?} should fail with {:?}, got {:?}", line, message, other.map(|r| r.to_owned())),
+            }
+        }
+
+        check(r#"{"msg":"x"}"#, Component::Time, "\"time\"");
+        check(r#"{"time":"2021-03-09 12:15:13"}"#, Component::Time, "'/'");
+        check(r#"{"time":"2021/03/09 12:15:13.145 +08:00" "msg":"x"}"#, Component::Time, "',' or '}'");
+        check(r#"{"time":"2021/03/09 12:15:13.145 +08:00",msg:"x"}"#, Component::Field(0), "key");
+        check(r#"{"time":"2021/03/09 12:15:13.145 +08:00","a":}"#, Component::Field(0), "a value");
+        check(r#"{"time":"2021/03/09 12:15:13.145 +08:00","a":[1,"#, Component::Field(0), "array");
+        check(r#"{"time":"2021/03/09 12:15:13.145 +08:00"} x"#, Component::Record, "end of the line");
+    }
+}
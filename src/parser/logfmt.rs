@@ -0,0 +1,91 @@
+use tinyvec::TinyVec;
+
+use super::{Component, ParseError, artifacts::*, json::scan_time, scanner::{Scanner, char_need_quote}};
+
+impl<'a> LogRecordRef<'a> {
+    /// parse a logfmt line like `time="2018/12/15 14:20:11.015 +08:00" level=warn msg="Slow query" caller=session.go:1234 sql="..."`.
+    /// The quoted `time` (or `ts`) is required, the `level` (or `lvl`), `msg` (or `message`) and `caller` (or `source`)
+    /// are optional, and the rest keys become the fields.
+    pub fn parse_logfmt(s: &'a str) -> Result<Self, ParseError> {
+        let scanner = Scanner::over(s);
+        let mut time = None;
+        let mut level = LogLevel::Unknown;
+        let mut source = None;
+        let mut message = LogStr::Unquoted("");
+        let mut entries = TinyVec::<[LogFieldRef; TINY_VEC_THRESHOLD]>::default();
+
+        scanner.skip_space();
+        while !scanner.remain().trim_start().is_empty() {
+            scanner.parsing(Component::Field(entries.len()));
+            let key = scanner.consume_until(|c| c == '=' || char_need_quote(c))?;
+            if key.is_empty() {
+                return Err(scanner.unexpected("a key", scanner.current_char()));
+            }
+            scanner.consume_exact('=')?;
+            match key {
+                "time" | "ts" => {
+                    scanner.parsing(Component::Time);
+                    time = Some(scan_time(&scanner)?);
+                }
+                "level" | "lvl" => {
+                    scanner.parsing(Component::Level);
+                    level = scan_value(&scanner)?.unescape().to_ascii_uppercase().parse()?;
+                }
+                "caller" | "source" => {
+                    scanner.parsing(Component::Source);
+                    source = FileLineRef::parse(scan_value(&scanner)?.unescape());
+                }
+                "msg" | "message" => {
+                    scanner.parsing(Component::Message);
+                    message = scan_value(&scanner)?;
+                }
+                _ => {
+                    let value = scan_value(&scanner)?;
+                    entries.push(LogFieldRef { key: LogStr::Unquoted(key), value });
+                }
+            }
+            match scanner.peek_char() {
+                Some(ch) if ch.is_whitespace() => scanner.skip_space(),
+                Some(ch) => return Err(scanner.unexpected("a space between pairs", ch)),
+                None => {}
+            }
+        }
+
+        scanner.parsing(Component::Time);
+        let time = time.ok_or_else(|| scanner.unexpected("the \"time\" key", "EOF"))?;
+        Ok(Self { level, time, source, message, entries })
+    }
+}
+
+/// scan a quoted value, or an unquoted value till the next space.
+fn scan_value<'a>(scanner: &Scanner<'a>) -> Result<LogStr<'a>, ParseError> {
+    match scanner.peek_char() {
+        Some('"') => Ok(LogStr::Quoted(scanner.quoted_string()?)),
+        _ => Ok(LogStr::Unquoted(scanner.consume_until(|c| c.is_whitespace())?)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parser::artifacts::{FileLineRef, LogLevel, LogRecordRef, LogStr};
+
+    #[test]
+    fn test_parse_logfmt() {
+        let line = r#"time="2018/12/15 14:20:11.015 +08:00" level=warn msg="Slow query" caller=session.go:1234 sql="SELECT * FROM TABLE\nWHERE ID=\"abc\"" duration=1.345s empty= "#;
+        let record = LogRecordRef::parse_logfmt(line).unwrap();
+        assert_eq!(record.level, LogLevel::Warn);
+        assert_eq!(record.time.time_str, "2018/12/15 14:20:11.015 +08:00");
+        assert_eq!(record.source, Some(FileLineRef { file: "session.go".into(), line: "1234".into() }));
+        assert_eq!(record.message, LogStr::Quoted(r#""Slow query""#));
+        let fields = record.entries.iter().map(|f| (f.key.unescape().into_owned(), f.value.unescape().into_owned())).collect::<Vec<_>>();
+        assert_eq!(fields, vec![
+            ("sql".to_owned(), "SELECT * FROM TABLE\nWHERE ID=\"abc\"".to_owned()),
+            ("duration".to_owned(), "1.345s".to_owned()),
+            ("empty".to_owned(), "".to_owned()),
+        ]);
+
+        assert!(LogRecordRef::parse_logfmt("level=info msg=x").is_err());
+        assert!(LogRecordRef::parse_logfmt(r#"ts="2018/12/15 14:20:11.015 +08:00""x=1"#).is_err());
+        assert!(LogRecordRef::parse_logfmt(r#"ts="2018/12/15 14:20:11.015 +08:00" =1"#).is_err());
+    }
+}
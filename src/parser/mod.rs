@@ -1,23 +1,135 @@
-use std::{error::{self}, fmt::{self, Display}};
+use std::{error::{self}, fmt::{self, Display, Write}};
 
 pub mod artifacts;
+pub mod diagnostics;
+pub mod json;
+pub mod logfmt;
+pub mod owned;
+pub mod reader;
+pub mod slow_log;
+pub mod sniff;
+pub mod summary;
+pub mod time;
+pub mod value;
 mod scanner;
 
+pub(crate) use scanner::char_need_quote;
+
+/// Component is the part of a record being parsed when an error happens.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Component {
+    Record,
+    Time,
+    Level,
+    Source,
+    Message,
+    /// the n-th (0-based) field of the record.
+    Field(usize),
+}
+
 #[derive(Debug)]
 pub enum ParseError {
     Unexpected {
         expected: String,
         got: String,
         hint: String,
+        /// the byte offset in the input where the error happens.
+        offset: usize,
+        /// the 1-based line number of the input, if known.
+        line: Option<usize>,
+        component: Component,
     },
     Empty
 }
 
+impl ParseError {
+    /// the reason of the error without the context, errors with the same reason are grouped in the summary.
+    pub fn reason(&self) -> String {
+        match self {
+            ParseError::Unexpected { expected, .. } => format!("excepting {}", expected),
+            ParseError::Empty => "got empty string to parse".to_owned(),
+        }
+    }
+
+    pub fn hint(&self) -> Option<&str> {
+        match self {
+            ParseError::Unexpected { hint, .. } => Some(hint),
+            ParseError::Empty => None,
+        }
+    }
+
+    /// attach the line number of the input to the error.
+    pub fn with_line(mut self, line_number: usize) -> Self {
+        if let ParseError::Unexpected { line, .. } = &mut self {
+            *line = Some(line_number);
+        }
+        self
+    }
+
+    /// render the error like rustc does, with a caret pointing to where the error happens in `source`,
+    /// which should be the input the error comes from.
+    /// Only a window around the error is printed, so it is still readable for giant lines.
+    pub fn render(&self, source: &str) -> String {
+        const WINDOW: usize = 40;
+
+        let (got, expected, offset, line, component) = match self {
+            ParseError::Unexpected { expected, got, offset, line, component, .. } => (got, expected, *offset, *line, *component),
+            ParseError::Empty => return format!("error: {}\n", self),
+        };
+        let offset = (0..=offset.min(source.len())).rev().find(|i| source.is_char_boundary(*i)).unwrap_or(0);
+        let printable = |c: char| if c.is_control() { ' ' } else { c };
+        let before = source[..offset].chars().rev().take(WINDOW + 1).collect::<Vec<_>>();
+        let after = source[offset..].chars().take(WINDOW + 1).collect::<Vec<_>>();
+
+        let mut snippet = String::new();
+        if before.len() > WINDOW {
+            snippet.push_str("...");
+        }
+        snippet.extend(before.iter().take(WINDOW).rev().map(|c| printable(*c)));
+        let caret_at = snippet.chars().count();
+        snippet.extend(after.iter().take(WINDOW).map(|c| printable(*c)));
+        if after.len() > WINDOW {
+            snippet.push_str("...");
+        }
+
+        let line_number = line.map(|l| l.to_string()).unwrap_or_default();
+        let gutter = " ".repeat(line_number.len());
+        let column = source[..offset].chars().count() + 1;
+        let mut out = String::new();
+        let _ = writeln!(out, "error: unexpected {}, excepting {}", got, expected);
+        match line {
+            Some(line) => { let _ = writeln!(out, "{}--> line {}, column {} (byte {}), while parsing {}", gutter, line, column, offset, component); }
+            None => { let _ = writeln!(out, "{}--> column {} (byte {}), while parsing {}", gutter, column, offset, component); }
+        }
+        let _ = writeln!(out, "{} |", gutter);
+        let _ = writeln!(out, "{} | {}", line_number, snippet);
+        let _ = writeln!(out, "{} | {}^", gutter, " ".repeat(caret_at));
+        out
+    }
+}
+
+impl Display for Component {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Component::Record => f.write_str("record"),
+            Component::Time => f.write_str("time"),
+            Component::Level => f.write_str("level"),
+            Component::Source => f.write_str("source"),
+            Component::Message => f.write_str("message"),
+            Component::Field(n) => write!(f, "field {}", n),
+        }
+    }
+}
+
 impl Display for ParseError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
-            ParseError::Unexpected { expected, got, hint } => {
-                write!(f, "unexpected {}, excepting {} (hint: `{}`)", got, expected, hint)?;
+            ParseError::Unexpected { expected, got, hint, offset, line, component } => {
+                write!(f, "unexpected {}, excepting {} while parsing {} at ", got, expected, component)?;
+                if let Some(line) = line {
+                    write!(f, "line {}, ", line)?;
+                }
+                write!(f, "byte {} (hint: `{}`)", offset, hint)?;
             }
             ParseError::Empty => { f.write_str("got empty string to parse")?; }
         }
@@ -26,3 +138,56 @@ impl Display for ParseError {
 }
 
 impl error::Error for ParseError {}
+
+#[cfg(test)]
+mod tests {
+    use super::{Component, ParseError, artifacts::LogRecordRef};
+
+    fn parse_error(line: &str) -> ParseError {
+        LogRecordRef::parse(line).expect_err(line).with_line(42)
+    }
+
+    #[test]
+    fn test_error_location() {
+        fn check(line: &str, component: Component, offset: usize) {
+            match parse_error(line) {
+                ParseError::Unexpected { component: c, offset: o, line: l, .. } => {
+                    assert_eq!((c, o, l), (component, offset, Some(42)), "failed to check {}", line)
+                }
+                other => panic!("unexpected error {} of {}", other, line),
+            }
+        }
+
+        check("[2018/12/15 14:20:11 +08:00] [INFO] [a.rs:1] [\"x\"]", Component::Time, 20);
+        check("[2018/12/15 14:20:11.015 +08:00] INFO [a.rs:1] [\"x\"]", Component::Level, 33);
+        check("[2018/12/15 14:20:11.015 +08:00] [INFO] a.rs:1 [\"x\"]", Component::Source, 40);
+        check("[2018/12/15 14:20:11.015 +08:00] [INFO] [a.rs:1] [\"x]", Component::Message, 50);
+        check("[2018/12/15 14:20:11.015 +08:00] [INFO] [a.rs:1] [\"µs\"] [a=1] [b=\"2", Component::Field(1), 66);
+        // the errors at the end of the line have the location too.
+        check("[2018/12/15 14:20:11.015 +08:00] [INFO", Component::Level, 34);
+        check("[2018/12/15 14:20:11.015 +08:00] [INFO] [a.rs:1] [", Component::Message, 50);
+        check("[2018/12/15 14:20:11.015 +08:00] [INFO] [a.rs:1] [x] [a=1", Component::Field(0), 57);
+    }
+
+    #[test]
+    fn test_render() {
+        let line = "[2018/12/15 14:20:11.015 +08:00] [INFO] tikv-server.rs:13 [\"TiKV Started\"]";
+        assert_eq!(parse_error(line).render(line), concat!(
+            "error: unexpected t, excepting [\n",
+            "  --> line 42, column 41 (byte 40), while parsing source\n",
+            "   |\n",
+            "42 | [2018/12/15 14:20:11.015 +08:00] [INFO] tikv-server.rs:13 [\"TiKV Started\"]\n",
+            "   |                                         ^\n",
+        ));
+
+        let line = format!("[2018/12/15 14:20:11.015 +08:00] [INFO] [a.rs:1] [\"{}\"] [µ=\"{}", "x".repeat(100), "y".repeat(100));
+        let rendered = parse_error(&line).render(&line);
+        assert_eq!(rendered, format!(concat!(
+            "error: unexpected \", excepting ]\n",
+            "  --> line 42, column 158 (byte 158), while parsing field 0\n",
+            "   |\n",
+            "42 | ...{}\"] [µ=\"{}...\n",
+            "   |                                            ^\n",
+        ), "x".repeat(34), "y".repeat(39)));
+    }
+}
@@ -0,0 +1,170 @@
+use std::borrow::Cow;
+
+use tinyvec::TinyVec;
+
+use super::{artifacts::*, time::Timestamp};
+
+/// LogString is the owned version of `LogStr`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum LogString {
+    Quoted(String),
+    Unquoted(String),
+}
+
+/// LogField is the owned version of `LogFieldRef`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct LogField {
+    pub key: LogString,
+    pub value: LogString,
+}
+
+/// FileLine is the owned version of `FileLineRef`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct FileLine {
+    pub file: String,
+    pub line: String,
+}
+
+/// Time is the owned version of `TimeRef`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Time {
+    pub time_str: String,
+    pub timestamp: Timestamp,
+}
+
+/// LogRecord is the owned version of `LogRecordRef`, which can be buffered, sorted or sent to other threads.
+/// Use `LogRecordRef::to_owned` to build it, and `LogRecord::borrowed` to view it as a `LogRecordRef` again,
+/// so all things working over `LogRecordRef` (i.e. the JSON writer) work over it too.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogRecord {
+    pub level: LogLevel,
+    pub time: Time,
+    pub message: LogString,
+    pub source: Option<FileLine>,
+    pub entries: Vec<LogField>,
+}
+
+impl LogString {
+    pub fn borrowed(&self) -> LogStr<'_> {
+        match self {
+            Self::Quoted(s) => LogStr::Quoted(s),
+            Self::Unquoted(s) => LogStr::Unquoted(s),
+        }
+    }
+}
+
+impl LogField {
+    pub fn borrowed(&self) -> LogFieldRef<'_> {
+        LogFieldRef { key: self.key.borrowed(), value: self.value.borrowed() }
+    }
+}
+
+impl FileLine {
+    pub fn borrowed(&self) -> FileLineRef<'_> {
+        FileLineRef { file: Cow::Borrowed(&self.file), line: Cow::Borrowed(&self.line) }
+    }
+}
+
+impl Time {
+    pub fn borrowed(&self) -> TimeRef<'_> {
+        TimeRef { time_str: &self.time_str, timestamp: self.timestamp }
+    }
+
+    pub fn epoch_nanos(&self) -> i128 {
+        self.timestamp.epoch_nanos()
+    }
+}
+
+impl LogRecord {
+    pub fn borrowed(&self) -> LogRecordRef<'_> {
+        LogRecordRef {
+            level: self.level,
+            time: self.time.borrowed(),
+            message: self.message.borrowed(),
+            source: self.source.as_ref().map(FileLine::borrowed),
+            entries: self.entries.iter().map(LogField::borrowed).collect::<TinyVec<_>>(),
+        }
+    }
+}
+
+impl<'a> LogStr<'a> {
+    pub fn to_owned(&self) -> LogString {
+        match self {
+            Self::Quoted(s) => LogString::Quoted(s.to_string()),
+            Self::Unquoted(s) => LogString::Unquoted(s.to_string()),
+        }
+    }
+}
+
+impl<'a> LogFieldRef<'a> {
+    pub fn to_owned(&self) -> LogField {
+        LogField { key: self.key.to_owned(), value: self.value.to_owned() }
+    }
+}
+
+impl<'a> FileLineRef<'a> {
+    pub fn to_owned(&self) -> FileLine {
+        FileLine { file: self.file.to_string(), line: self.line.to_string() }
+    }
+}
+
+impl<'a> TimeRef<'a> {
+    pub fn to_owned(&self) -> Time {
+        Time { time_str: self.time_str.to_owned(), timestamp: self.timestamp }
+    }
+}
+
+impl<'a> LogRecordRef<'a> {
+    pub fn to_owned(&self) -> LogRecord {
+        LogRecord {
+            level: self.level,
+            time: self.time.to_owned(),
+            message: self.message.to_owned(),
+            source: self.source.as_ref().map(FileLineRef::to_owned),
+            entries: self.entries.iter().map(LogFieldRef::to_owned).collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use crate::{json_writer::ToJSON, parser::artifacts::with_log_record};
+
+    use super::LogRecord;
+
+    const LINE: &str = r#"[2018/12/15 14:20:11.015 +08:00] [WARN] [session.go:1234] ["Slow query"] [sql="SELECT * FROM TABLE\nWHERE ID=\"abc\""] [duration=1.345s] [client=192.168.0.123:12345] [txn_id=123000102231]"#;
+
+    fn to_json(r: impl ToJSON) -> String {
+        let mut buf = Vec::new();
+        r.write_json_to(&mut buf).unwrap();
+        String::from_utf8(buf).unwrap()
+    }
+
+    #[test]
+    fn test_owned_round_trip() {
+        let (record, json) = with_log_record(LINE, |r| (r.to_owned(), to_json(&r))).unwrap();
+        assert_eq!(record.entries.len(), 4);
+        assert_eq!(record.source.as_ref().unwrap().file, "session.go");
+        assert_eq!(to_json(record.borrowed()), json);
+        with_log_record(LINE, |r| assert_eq!(r.to_owned(), record)).unwrap();
+        assert_eq!(record.borrowed().to_owned(), record);
+    }
+
+    #[test]
+    fn test_owned_across_threads() {
+        let mut records = thread::spawn(|| {
+            let later = LINE.replace("14:20:11.015", "14:20:12.000");
+            vec![&later, LINE]
+                .into_iter()
+                .map(|line| with_log_record(line, |r| r.to_owned()).unwrap())
+                .collect::<Vec<LogRecord>>()
+        })
+        .join()
+        .unwrap();
+        records.sort_by_key(|r| r.time.epoch_nanos());
+        assert_eq!(records[0].time.time_str, "2018/12/15 14:20:11.015 +08:00");
+        assert_eq!(records[1].time.time_str, "2018/12/15 14:20:12.000 +08:00");
+    }
+}
@@ -0,0 +1,520 @@
+use std::{io::{self, BufRead}, mem};
+
+use quick_error::quick_error;
+
+use super::{ParseError, artifacts::{LevelFilter, LogFieldRef, LogRecordRef, LogStr, RecordHeader, is_record_header}, diagnostics::{Diagnostics, Stderr, WithLine}, owned::LogRecord};
+
+quick_error! {
+    #[derive(Debug)]
+    pub enum ReadError {
+        Io(err: io::Error) {
+            from()
+            source(err)
+            display("I/O Error: {}", err)
+        }
+        Parse { line: usize, err: ParseError } {
+            source(err)
+            display("Error during parsing line {}: {}", line, err)
+        }
+    }
+}
+
+/// Continuation decides where the lines without a `[timestamp]` header go.
+/// Such lines are printed by some older TiKV / TiDB versions or panics, which write raw newlines into a field.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Continuation {
+    /// attach the continuation lines to the record as a field with the key.
+    Field(String),
+    /// append the continuation lines to the value of the last field, or the message if there isn't any field.
+    AppendToLastValue,
+}
+
+impl Default for Continuation {
+    fn default() -> Self {
+        Continuation::Field("_continuation".to_owned())
+    }
+}
+
+/// LogReader reads records of the unified log format from any `BufRead`.
+///
+/// Lines that don't start with a `[timestamp]` header are assembled into the record before them,
+/// see `Continuation` for how they are attached.
+///
+/// `next_record` yields records borrowing the buffers of the reader, the buffers are reused
+/// between records, so no allocation is needed in the steady state.
+/// When the records need to outlive the next read, use the reader as an `Iterator` of owned `LogRecord`s.
+///
+/// Fields failed to parse are skipped and sent to the `Diagnostics`, which prints them to stderr by default.
+pub struct LogReader<R, D = Stderr> {
+    input: R,
+    continuation: Continuation,
+    diagnostics: D,
+    level_filter: LevelFilter,
+    /// whether a line starts a new record instead of continuing the record before it.
+    starts_record: fn(&str) -> bool,
+    /// the text of the current record, the header line and then the continuation lines.
+    line: String,
+    /// the length of the header line in `line`.
+    header_len: usize,
+    line_number: usize,
+    /// the next header line read ahead while looking for continuation lines.
+    pending: String,
+    /// the line number of `pending`, if there is a pending line.
+    pending_line_number: Option<usize>,
+    lines_read: usize,
+    /// the buffer of the value with continuation lines appended.
+    appended: String,
+}
+
+impl<R: BufRead> LogReader<R> {
+    pub fn new(input: R) -> Self {
+        Self {
+            input,
+            continuation: Continuation::default(),
+            diagnostics: Stderr,
+            level_filter: LevelFilter::all(),
+            starts_record: is_record_header,
+            line: String::new(),
+            header_len: 0,
+            line_number: 0,
+            pending: String::new(),
+            pending_line_number: None,
+            lines_read: 0,
+            appended: String::new(),
+        }
+    }
+
+}
+
+impl<R: BufRead, D: Diagnostics> LogReader<R, D> {
+    pub fn with_continuation(mut self, continuation: Continuation) -> Self {
+        self.continuation = continuation;
+        self
+    }
+
+    /// only read the records whose levels are kept by the filter, the others are skipped
+    /// right after their levels are parsed.
+    pub fn with_level_filter(mut self, level_filter: LevelFilter) -> Self {
+        self.level_filter = level_filter;
+        self
+    }
+
+    /// lines for which `starts_record` is true start new records, instead of being continuation lines
+    /// of the record before them. By default only the lines with a `[timestamp]` header start records.
+    /// Only the records with a `[timestamp]` header have continuation lines.
+    pub fn with_record_start(mut self, starts_record: fn(&str) -> bool) -> Self {
+        self.starts_record = starts_record;
+        self
+    }
+
+    /// the input starts after `lines` lines, i.e. it is sought to a record, which are still counted in the line numbers.
+    pub fn with_lines_skipped(mut self, lines: usize) -> Self {
+        self.lines_read = lines;
+        self
+    }
+
+    pub fn with_diagnostics<D2: Diagnostics>(self, diagnostics: D2) -> LogReader<R, D2> {
+        LogReader {
+            input: self.input,
+            continuation: self.continuation,
+            diagnostics,
+            level_filter: self.level_filter,
+            starts_record: self.starts_record,
+            line: self.line,
+            header_len: self.header_len,
+            line_number: self.line_number,
+            pending: self.pending,
+            pending_line_number: self.pending_line_number,
+            lines_read: self.lines_read,
+            appended: self.appended,
+        }
+    }
+
+    pub fn diagnostics(&self) -> &D {
+        &self.diagnostics
+    }
+
+    pub fn diagnostics_mut(&mut self) -> &mut D {
+        &mut self.diagnostics
+    }
+
+    /// the 1-based line number of the first line of the current record, `0` if nothing has been read.
+    pub fn line_number(&self) -> usize {
+        self.line_number
+    }
+
+    /// the text of the current record, continuation lines included, without the line ending.
+    pub fn current_line(&self) -> &str {
+        &self.line
+    }
+
+    /// read the next line into `buf` without the line ending, returns `false` on EOF.
+    fn read_raw_line(&mut self, buf: &mut String) -> io::Result<bool> {
+        if self.input.read_line(buf)? == 0 {
+            return Ok(false);
+        }
+        self.lines_read += 1;
+        if buf.ends_with('\n') {
+            buf.pop();
+            if buf.ends_with('\r') {
+                buf.pop();
+            }
+        }
+        Ok(true)
+    }
+
+    /// read the next line into the line buffer and returns it, the lines are not assembled into records.
+    pub fn next_line(&mut self) -> Option<io::Result<&str>> {
+        self.line.clear();
+        match self.pending_line_number.take() {
+            Some(line_number) => {
+                mem::swap(&mut self.line, &mut self.pending);
+                self.line_number = line_number;
+            }
+            None => {
+                let mut line = mem::take(&mut self.line);
+                let read = self.read_raw_line(&mut line);
+                self.line = line;
+                match read {
+                    Ok(false) => return None,
+                    Err(err) => return Some(Err(err)),
+                    Ok(true) => {}
+                }
+                self.line_number = self.lines_read;
+            }
+        }
+        self.header_len = self.line.len();
+        Some(Ok(&self.line))
+    }
+
+    /// read the header line and all continuation lines of the next record into the line buffer,
+    /// blank lines before the header are skipped. Returns `false` on EOF.
+    fn assemble(&mut self) -> io::Result<bool> {
+        if !self.read_header()? {
+            return Ok(false);
+        }
+        self.read_continuations(true)?;
+        Ok(true)
+    }
+
+    /// read the first non-blank line into the line buffer, returns `false` on EOF.
+    fn read_header(&mut self) -> io::Result<bool> {
+        let mut line = mem::take(&mut self.line);
+        line.clear();
+        match self.pending_line_number.take() {
+            Some(line_number) => {
+                mem::swap(&mut line, &mut self.pending);
+                self.line_number = line_number;
+            }
+            None => loop {
+                line.clear();
+                if !self.read_raw_line(&mut line)? {
+                    self.line = line;
+                    return Ok(false);
+                }
+                if !line.trim().is_empty() {
+                    self.line_number = self.lines_read;
+                    break;
+                }
+            },
+        }
+        self.header_len = line.len();
+        self.line = line;
+        Ok(true)
+    }
+
+    /// read the continuation lines after the header line, which are appended to the line buffer if `keep` is true,
+    /// or dropped otherwise.
+    fn read_continuations(&mut self, keep: bool) -> io::Result<()> {
+        // an orphan line without header can't be assembled, let it fail on parsing alone.
+        if !is_record_header(&self.line) {
+            return Ok(());
+        }
+        let mut line = mem::take(&mut self.line);
+        let mut next = mem::take(&mut self.pending);
+        let result = loop {
+            next.clear();
+            match self.read_raw_line(&mut next) {
+                Ok(true) => {}
+                Ok(false) => break Ok(()),
+                Err(err) => break Err(err),
+            }
+            if (self.starts_record)(&next) {
+                self.pending_line_number = Some(self.lines_read);
+                break Ok(());
+            }
+            if keep {
+                line.push('\n');
+                line.push_str(&next);
+            }
+        };
+        self.pending = next;
+        // drop the trailing blank lines between records.
+        let len = line.trim_end().len().max(self.header_len);
+        line.truncate(len);
+        self.line = line;
+        result
+    }
+
+    /// read the next record whose level is kept by the level filter, with its header parsed.
+    /// The continuation lines of the records dropped are skipped without being kept.
+    /// Records whose headers fail to parse are kept, so the errors are reported by parsing them.
+    fn read_kept(&mut self) -> io::Result<Option<Result<RecordHeader, ParseError>>> {
+        loop {
+            if !self.read_header()? {
+                return Ok(None);
+            }
+            let header = RecordHeader::parse(&self.line);
+            let kept = header.as_ref().map_or(true, |header| self.level_filter.accepts(header.level));
+            self.read_continuations(kept)?;
+            if kept {
+                return Ok(Some(header));
+            }
+        }
+    }
+
+    /// read and parse the next record, blank lines between records are skipped.
+    pub fn next_record(&mut self) -> Option<Result<LogRecordRef<'_>, ReadError>> {
+        self.next_numbered_record().map(|(_, record)| record)
+    }
+
+    /// read the next record without parsing it, returns the line number where it starts.
+    /// The text is `current_line`, which can be parsed by `parse_current` if it has a `[timestamp]` header.
+    /// The level filter isn't applied.
+    pub fn next_text(&mut self) -> Option<io::Result<usize>> {
+        match self.assemble() {
+            Ok(false) => None,
+            Ok(true) => Some(Ok(self.line_number)),
+            Err(err) => Some(Err(err)),
+        }
+    }
+
+    /// like `next_record`, with the line number where the record starts.
+    pub fn next_numbered_record(&mut self) -> Option<(usize, Result<LogRecordRef<'_>, ReadError>)> {
+        let header = match self.read_kept() {
+            Ok(None) => return None,
+            Err(err) => return Some((self.line_number, Err(err.into()))),
+            Ok(Some(header)) => header,
+        };
+        Some((self.line_number, self.parse_assembled(header)))
+    }
+
+    /// parse the record read, with its continuation lines attached.
+    pub fn parse_current(&mut self) -> Result<LogRecordRef<'_>, ReadError> {
+        let header = RecordHeader::parse(&self.line[..self.header_len]);
+        self.parse_assembled(header)
+    }
+
+    /// parse the rest of the record read after its header, with its continuation lines attached.
+    fn parse_assembled(&mut self, header: Result<RecordHeader, ParseError>) -> Result<LogRecordRef<'_>, ReadError> {
+        let line_number = self.line_number;
+        let (header_line, continuation) = self.line.split_at(self.header_len);
+        let diagnostics = WithLine { inner: &mut self.diagnostics, line: line_number };
+        let mut record = match header.and_then(|header| LogRecordRef::parse_rest(header_line, header, diagnostics)) {
+            Ok(record) => record,
+            Err(err) => return Err(ReadError::Parse { line: line_number, err: err.with_line(line_number) }),
+        };
+        if let Some(continuation) = continuation.strip_prefix('\n') {
+            match &self.continuation {
+                Continuation::Field(key) => record.entries.push(LogFieldRef {
+                    key: LogStr::Unquoted(key),
+                    value: LogStr::Unquoted(continuation),
+                }),
+                Continuation::AppendToLastValue => {
+                    let last = match record.entries.last_mut() {
+                        Some(field) => &mut field.value,
+                        None => &mut record.message,
+                    };
+                    self.appended.clear();
+                    self.appended.push_str(&last.unescape());
+                    self.appended.push('\n');
+                    self.appended.push_str(continuation);
+                    *last = LogStr::Unquoted(&self.appended);
+                }
+            }
+        }
+        Ok(record)
+    }
+
+    pub fn into_inner(self) -> R {
+        self.input
+    }
+}
+
+impl<R: BufRead, D: Diagnostics> Iterator for LogReader<R, D> {
+    type Item = Result<LogRecord, ReadError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_record().map(|r| r.map(|r| r.to_owned()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::{Continuation, LogReader, ReadError};
+
+    const INPUT: &str = "[2018/12/15 14:20:11.015 +08:00] [INFO] [tikv-server.rs:13] [\"TiKV Started\"]\r\n\
+        \n\
+        [2018/12/15 14:20:11.015 +08:00] [WARN] [session.go:1234] [\"Slow query\"] [duration=1.345s]\n\
+        [2018/12/15 14:20:11] [WARN] [session.go:1234] [\"Bad time\"]\n\
+        [2018/12/15 14:20:12.000 +08:00] [ERROR] [<unknown>] [\"No newline at the end\"]";
+
+    const PANIC: &str = "[2018/12/15 14:20:11.015 +08:00] [FATAL] [panic_hook.rs:45] [\"TiKV panic\"] [error=\"index out of bounds\"]\n\
+        stack backtrace:\n\
+        \x20  0: std::sys::imp::backtrace::tracing::imp::unwind_backtrace\n\
+        \n\
+        \x20            at /checkout/src/libstd/sys/unix/backtrace/tracing/gcc_s.rs:49\n\
+        \n\
+        [2018/12/15 14:20:12.000 +08:00] [INFO] [tikv-server.rs:13] [\"TiKV Started\"]\n";
+
+    const BACKTRACE: &str = "stack backtrace:\n\
+        \x20  0: std::sys::imp::backtrace::tracing::imp::unwind_backtrace\n\
+        \n\
+        \x20            at /checkout/src/libstd/sys/unix/backtrace/tracing/gcc_s.rs:49";
+
+    #[test]
+    fn test_next_record() {
+        let mut reader = LogReader::new(Cursor::new(INPUT));
+        let record = reader.next_record().unwrap().unwrap();
+        assert_eq!(record.message.unescape(), "TiKV Started");
+        assert_eq!(reader.line_number(), 1);
+
+        let record = reader.next_record().unwrap().unwrap();
+        assert_eq!(record.entries.len(), 1);
+        assert_eq!(reader.line_number(), 3);
+
+        match reader.next_record().unwrap() {
+            Err(ReadError::Parse { line, .. }) => assert_eq!(line, 4),
+            other => panic!("unexpected result {:?}", other),
+        }
+        assert_eq!(reader.current_line(), "[2018/12/15 14:20:11] [WARN] [session.go:1234] [\"Bad time\"]");
+
+        let record = reader.next_record().unwrap().unwrap();
+        assert_eq!(record.message.unescape(), "No newline at the end");
+        assert!(record.source.is_none());
+        assert!(reader.next_record().is_none());
+    }
+
+    #[test]
+    fn test_iterate_owned() {
+        let records = LogReader::new(Cursor::new(INPUT)).collect::<Vec<_>>();
+        assert_eq!(records.len(), 4);
+        assert!(records[2].is_err());
+        let messages = records
+            .iter()
+            .filter_map(|r| r.as_ref().ok())
+            .map(|r| r.borrowed().message.unescape().into_owned())
+            .collect::<Vec<_>>();
+        assert_eq!(messages, vec!["TiKV Started", "Slow query", "No newline at the end"]);
+    }
+
+    #[test]
+    fn test_continuation_as_field() {
+        let mut reader = LogReader::new(Cursor::new(PANIC));
+        let record = reader.next_record().unwrap().unwrap();
+        assert_eq!(record.entries.len(), 2);
+        assert_eq!(record.entries[1].key.unescape(), "_continuation");
+        assert_eq!(record.entries[1].value.unescape(), BACKTRACE);
+        assert_eq!(reader.line_number(), 1);
+
+        let record = reader.next_record().unwrap().unwrap();
+        assert_eq!(record.message.unescape(), "TiKV Started");
+        assert!(record.entries.is_empty());
+        assert_eq!(reader.line_number(), 7);
+        assert!(reader.next_record().is_none());
+    }
+
+    #[test]
+    fn test_continuation_appended() {
+        let mut reader = LogReader::new(Cursor::new(PANIC)).with_continuation(Continuation::AppendToLastValue);
+        let record = reader.next_record().unwrap().unwrap();
+        assert_eq!(record.entries.len(), 1);
+        assert_eq!(record.entries[0].value.unescape(), format!("index out of bounds\n{}", BACKTRACE));
+
+        let input = "[2018/12/15 14:20:11.015 +08:00] [FATAL] [panic_hook.rs:45] [\"TiKV\\tpanic\"]\ncontinued";
+        let mut reader = LogReader::new(Cursor::new(input)).with_continuation(Continuation::AppendToLastValue);
+        let record = reader.next_record().unwrap().unwrap();
+        assert_eq!(record.message.unescape(), "TiKV\tpanic\ncontinued");
+    }
+
+    #[test]
+    fn test_orphan_lines() {
+        let input = "orphan 1\norphan 2\n[2018/12/15 14:20:11.015 +08:00] [INFO] [tikv-server.rs:13] [\"TiKV Started\"]";
+        let records = LogReader::new(Cursor::new(input)).collect::<Vec<_>>();
+        assert_eq!(records.len(), 3);
+        assert!(matches!(records[0], Err(ReadError::Parse { line: 1, .. })));
+        assert!(matches!(records[1], Err(ReadError::Parse { line: 2, .. })));
+        assert!(records[2].is_ok());
+    }
+
+    #[test]
+    fn test_numbered_records() {
+        let input = "\n[2018/12/15 14:20:11.015 +08:00] [INFO] [a.rs:1] [\"x\"]\nat a.rs\n\n[2018/12/15 14:20:12.015 +08:00] [INFO] [a.rs:1] [\"y\"]\n";
+        let mut reader = LogReader::new(Cursor::new(input));
+        let mut lines = Vec::new();
+        while let Some((line, record)) = reader.next_numbered_record() {
+            record.unwrap();
+            lines.push(line);
+        }
+        assert_eq!(lines, vec![2, 5]);
+    }
+
+    #[test]
+    fn test_record_start() {
+        use crate::parser::sniff::is_record_start;
+
+        let header = "[2018/12/15 14:20:11.015 +08:00] [INFO] [a.rs:1] [\"x\"]";
+        let json = r#"{"time":"2018/12/15 14:20:11.016 +08:00","msg":"y"}"#;
+        let logfmt = r#"time="2018/12/15 14:20:11.017 +08:00" msg=z"#;
+        let input = format!("{}\nat a.rs\n{{\"a\":1}}\nk=v\n{}\nplain\n{}\n{}\n{{\"a\":1}}\n\nk=v\n", header, json, header, logfmt);
+        let mut reader = LogReader::new(Cursor::new(input)).with_record_start(is_record_start);
+        let mut texts = Vec::new();
+        while let Some(line) = reader.next_text() {
+            texts.push((line.unwrap(), reader.current_line().to_owned()));
+        }
+        // the lines which look like JSON or logfmt but don't parse are continuation lines of a unified log record.
+        assert_eq!(texts, vec![
+            (1, format!("{}\nat a.rs\n{{\"a\":1}}\nk=v", header)),
+            (5, json.to_owned()),
+            (6, "plain".to_owned()),
+            (7, header.to_owned()),
+            (8, logfmt.to_owned()),
+            (9, "{\"a\":1}".to_owned()),
+            (11, "k=v".to_owned()),
+        ]);
+    }
+
+    #[test]
+    fn test_level_filter() {
+        use crate::parser::artifacts::{LevelFilter, LogLevel};
+
+        let input = "[2018/12/15 14:20:11.015 +08:00] [DEBUG] [a.rs:1] [\"debug\"]\n\
+            [2018/12/15 14:20:11.016 +08:00] [WARN] [a.rs:1] [\"warn\"]\n\
+            at a.rs\n\
+            [2018/12/15 14:20:11.017 +08:00] [INFO] [a.rs:1] this is not kept\n\
+            [2018/12/15 14:20:11.018 +08:00] [FATAL] [a.rs:1] [\"fatal\"]\n\
+            [2018/12/15 14:20:11] [INFO] [a.rs:1] [\"malformed\"]\n\
+            [2018/12/15 14:20:11.019 +08:00] [ERROR] [a.rs:1] [\"error\"]\n";
+        let read = |filter| {
+            let mut reader = LogReader::new(Cursor::new(input)).with_level_filter(filter);
+            let mut read = Vec::new();
+            while let Some((line, record)) = reader.next_numbered_record() {
+                read.push((line, record.map(|r| r.message.unescape().into_owned()).unwrap_or_else(|_| "<error>".to_owned())));
+            }
+            read
+        };
+        let read = |filter| read(filter).into_iter().map(|(line, msg)| format!("{}:{}", line, msg)).collect::<Vec<_>>();
+
+        assert_eq!(read(LevelFilter::at_least(LogLevel::Warn)), vec!["2:warn", "5:fatal", "6:<error>", "7:error"]);
+        assert_eq!(read(LevelFilter::only([LogLevel::Error, LogLevel::Debug])), vec!["1:debug", "6:<error>", "7:error"]);
+        assert_eq!(read(LevelFilter::at_least(LogLevel::Warn).and(LevelFilter::only([LogLevel::Fatal, LogLevel::Info]))), vec!["5:fatal", "6:<error>"]);
+        assert_eq!(read(LevelFilter::all()).len(), 6);
+
+        // the continuation lines of the records dropped aren't kept.
+        let mut reader = LogReader::new(Cursor::new(input)).with_level_filter(LevelFilter::only([LogLevel::Info]));
+        assert!(reader.next_record().unwrap().is_err());
+        assert_eq!(reader.current_line(), "[2018/12/15 14:20:11.017 +08:00] [INFO] [a.rs:1] this is not kept");
+    }
+}
@@ -1,11 +1,12 @@
 use std::{cell::Cell};
 
-use super::{ParseError};
+use super::{Component, ParseError};
 
 pub struct Scanner<'a> {
     target: &'a str,
     remain: Cell<&'a str>,
-    offset: Cell<usize>
+    offset: Cell<usize>,
+    component: Cell<Component>,
 }
 
 impl<'a> Scanner<'a> {
@@ -13,44 +14,81 @@ impl<'a> Scanner<'a> {
         Scanner {
             target: s,
             remain: Cell::new(s),
-            offset: Cell::new(0)
+            offset: Cell::new(0),
+            component: Cell::new(Component::Record),
         }
     }
 
+    /// mark the component being parsed, which is reported by errors.
+    pub fn parsing(&self, component: Component) {
+        self.component.set(component)
+    }
+
     pub fn is_done(&self) -> bool {
         self.offset.get() >= self.target.len()
     }
 
+    /// the whole text being scanned.
+    pub fn target(&self) -> &'a str {
+        self.target
+    }
+
     pub fn remain(&self) -> &'a str {
-        return self.remain.get()
+        self.remain.get()
+    }
+
+    /// the byte offset of the next char to be scanned.
+    pub fn offset(&self) -> usize {
+        self.offset.get()
+    }
+
+    /// the text already scanned since the offset `start`.
+    pub fn since(&self, start: usize) -> &'a str {
+        &self.target[start..self.offset.get()]
     }
 
     pub fn consume(&self, n: usize) -> Result<&'a str, ParseError> {
         let new_offset = self.offset.get() + n;
         if new_offset > self.target.len() {
-            return Err(ParseError::Empty)
+            return Err(self.unexpected(format!("{} more bytes", new_offset - self.target.len()), "EOF"))
         }
         self.offset.set(self.offset.get() + n);
         let (consumed, remain) = self.remain.get().split_at(n);
         self.remain.set(remain);
-        return Ok(consumed)
+        Ok(consumed)
     }
 
     pub fn drain(&self) -> Result<&'a str, ParseError> {
-        return self.consume(self.target.len() - self.offset.get())
+        self.consume(self.target.len() - self.offset.get())
     } 
 
     pub fn peek_char(&self) -> Option<char> {
-        return self.remain().chars().next()
+        self.remain().chars().next()
     }
 
     pub fn consume_until(&self, mut f: impl FnMut(char) -> bool) -> Result<&'a str, ParseError> {
         for (i, ch) in self.remain().char_indices() {
             if f(ch) {
-                return Ok(self.consume(i)?);
+                return self.consume(i);
             }
         }
-        return Ok(self.drain()?)
+        self.drain()
+    }
+
+    /// consume exactly `n` ASCII digits and returns their decimal value.
+    pub fn digits(&self, n: usize, what: &str) -> Result<u32, ParseError> {
+        let mut value = 0u32;
+        for _ in 0..n {
+            match self.peek_char() {
+                Some(ch) if ch.is_ascii_digit() => {
+                    value = value * 10 + ch.to_digit(10).unwrap();
+                    self.consume(1)?;
+                }
+                Some(ch) => return Err(self.unexpected(format!("digit of {}", what), ch)),
+                None => return Err(self.unexpected(format!("digit of {}", what), "EOF")),
+            }
+        }
+        Ok(value)
     }
 
     pub fn unquoted_string(&self) -> Result<&'a str, ParseError> {
@@ -65,7 +103,10 @@ impl<'a> Scanner<'a> {
         ParseError::Unexpected {
             expected: expected.to_string(),
             got: got.to_string(),
-            hint: format!("{}>{}<{}", self.context_before(), self.current_char(), self.context_after())
+            hint: format!("{}>{}<{}", self.context_before(), self.current_char(), self.context_after()),
+            offset: self.offset.get(),
+            line: None,
+            component: self.component.get(),
         }
     }
 
@@ -74,7 +115,7 @@ impl<'a> Scanner<'a> {
         match self.offset.get() {
             0 => "^",
             x if x <= amount => &self.target[..self.offset.get()],
-            _ => &self.target[self.offset.get()-amount..self.offset.get()]
+            x => &self.target[floor_char_boundary(self.target, x - amount)..x]
         }
     }
 
@@ -83,7 +124,7 @@ impl<'a> Scanner<'a> {
         match self.target.len() - self.offset.get() {
             0 => "",
             x if x <= amount => &self.remain()[..x],
-            _ => &self.remain()[..amount]
+            _ => &self.remain()[..floor_char_boundary(self.remain(), amount)]
         }
     }
 
@@ -99,7 +140,7 @@ impl<'a> Scanner<'a> {
                 State::Scanning => {
                     match ch {
                         // split at i + 1 to include the close '"' char.
-                        '"' => return Ok(self.consume(i+1)?),
+                        '"' => return self.consume(i+1),
                         '\\' => state = State::Escaping,
                         _ => continue
                     };
@@ -120,7 +161,7 @@ impl<'a> Scanner<'a> {
         match self.remain().chars().next() {
             Some(ch) if ch == expected => Ok(()),
             Some(ch) => Err(self.unexpected(expected, ch)),
-            None => Err(empty())
+            None => Err(self.unexpected(expected, "EOF"))
         }
     }
 
@@ -149,23 +190,21 @@ impl<'a> Scanner<'a> {
         self.skip_while(char::is_whitespace)
     }
 
-    pub fn till_next_bracket(& self) -> Result<&str, ParseError> {
+    pub fn till_next_bracket(& self) -> Result<&'a str, ParseError> {
         for (i, ch) in self.remain().char_indices() {
             if ch == ']' {
-                return Ok(self.consume(i)?)
+                return self.consume(i)
             }
         }
-        Err(empty())
+        Err(self.unexpected("']'", "EOF"))
     }
 }
 
 pub fn char_need_quote(ch: char) -> bool {
-    match ch {
-        '\x00'..='\x20' | '=' | '"' | '[' | ']'  => true,
-        _ => false,
-    }
+    matches!(ch, '\x00'..='\x20' | '=' | '"' | '[' | ']')
 }
 
-pub fn empty() -> ParseError {
-    ParseError::Empty
-}
\ No newline at end of file
+/// the largest char boundary of `s` not greater than `i`.
+fn floor_char_boundary(s: &str, i: usize) -> usize {
+    (0..=i).rev().find(|i| s.is_char_boundary(*i)).unwrap_or(0)
+}
@@ -0,0 +1,167 @@
+use tinyvec::TinyVec;
+
+use super::{Component, ParseError, artifacts::*, scanner::Scanner, time::Timestamp};
+
+/// the header keys whose values are the rest of the line, which may have spaces.
+const WHOLE_LINE_KEYS: &[&str] = &["User@Host", "Prev_stmt", "Warnings"];
+
+impl<'a> LogRecordRef<'a> {
+    /// parse a block of the slow query log of TiDB, like
+    ///
+    /// ```text
+    /// # Time: 2019-08-14T09:26:59.487776265+08:00
+    /// # Txn_start_ts: 410450924122144769
+    /// # Query_time: 1.527627037
+    /// # Process_time: 0.07 Request_count: 1 Total_keys: 131073
+    /// use test;
+    /// insert into t select * from t;
+    /// ```
+    ///
+    /// The `# Time:` header is required, and the other `Key: value` pairs of the header lines become the fields.
+    /// A word without ':' continues the value before it, or takes the rest of the line as its value when it starts a line.
+    /// The SQL after the header lines is the message, without the `use <db>;` line before it.
+    /// Slow query logs have neither levels nor sources.
+    pub fn parse_slow_log(s: &'a str) -> Result<Self, ParseError> {
+        let scanner = Scanner::over(s);
+        let mut time = None;
+        let mut entries = TinyVec::<[LogFieldRef; TINY_VEC_THRESHOLD]>::default();
+
+        while scanner.peek_char() == Some('#') {
+            scanner.consume_exact('#')?;
+            // where the value of the last pair of this line starts.
+            let mut value_start = None;
+            loop {
+                scanner.skip_while(|c| c == ' ' || c == '\t');
+                match scanner.peek_char() {
+                    None => break,
+                    Some('\n') => {
+                        scanner.consume_exact('\n')?;
+                        break;
+                    }
+                    Some(_) => {}
+                }
+                scanner.parsing(Component::Field(entries.len()));
+                let key = scanner.consume_until(|c| c == ':' || c.is_whitespace())?;
+                if key.is_empty() {
+                    return Err(scanner.unexpected("a key like `Query_time:`", scanner.current_char()));
+                }
+                if scanner.peek_char() != Some(':') {
+                    match (value_start, entries.last_mut()) {
+                        // a value with spaces, like `Backoff_types: [regionMiss txnLock]`.
+                        (Some(start), Some(last)) => last.value = LogStr::Unquoted(scanner.since(start)),
+                        // a key without ':', the rest of the line is its value.
+                        _ => {
+                            scanner.skip_while(|c| c == ' ' || c == '\t');
+                            let value = scanner.consume_until(|c| c == '\n')?.trim_end();
+                            entries.push(LogFieldRef { key: LogStr::Unquoted(key), value: LogStr::Unquoted(value) });
+                        }
+                    }
+                    continue;
+                }
+                scanner.consume_exact(':')?;
+                scanner.skip_while(|c| c == ' ' || c == '\t');
+                if key == "Time" {
+                    scanner.parsing(Component::Time);
+                    let start = scanner.offset();
+                    let timestamp = Timestamp::scan_rfc3339_from(&scanner)?;
+                    time = Some(TimeRef { time_str: scanner.since(start), timestamp });
+                    value_start = None;
+                    continue;
+                }
+                value_start = Some(scanner.offset());
+                let value = if WHOLE_LINE_KEYS.contains(&key) {
+                    scanner.consume_until(|c| c == '\n')?.trim_end()
+                } else {
+                    scanner.consume_until(char::is_whitespace)?
+                };
+                entries.push(LogFieldRef { key: LogStr::Unquoted(key), value: LogStr::Unquoted(value) });
+            }
+        }
+
+        scanner.parsing(Component::Message);
+        let mut sql = scanner.remain().trim();
+        // the `use <db>;` line is written before the SQL when the database is set, which is the `DB` field.
+        if let Some((first, rest)) = sql.split_once('\n') {
+            if first.starts_with("use ") && first.trim_end().ends_with(';') {
+                sql = rest.trim_start();
+            }
+        }
+
+        scanner.parsing(Component::Time);
+        let time = time.ok_or_else(|| scanner.unexpected("the `# Time:` header", "EOF"))?;
+        Ok(Self { level: LogLevel::Unknown, time, source: None, message: LogStr::Unquoted(sql), entries })
+    }
+}
+
+/// whether the line is the `# Time:` header, which starts a block of the slow query log.
+pub fn is_slow_log_start(line: &str) -> bool {
+    line.starts_with("# Time:")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parser::{Component, ParseError, artifacts::{LogLevel, LogRecordRef}};
+
+    #[test]
+    fn test_parse_slow_log() {
+        let block = concat!(
+            "# Time: 2019-08-14T09:26:59.487776265+08:00\n",
+            "# Txn_start_ts: 410450924122144769\n",
+            "# User@Host: root[root] @ localhost [127.0.0.1]\n",
+            "# Query_time: 1.527627037\n",
+            "# Process_time: 0.07 Request_count: 1 Cop_proc_addr: 172.16.5.87:20171\n",
+            "# Stats: t:pseudo\n",
+            "# Backoff_types: [regionMiss txnLock] Backoff_total: 0.002\n",
+            "# Is_internal false\n",
+            "# Succ: true\n",
+            "# Plan: tidb_decode_plan('ZJAwCTMyXzcJMAkyMAlk')\n",
+            "use test;\n",
+            "insert into t\n",
+            "select * from t;\n",
+            "\n",
+        );
+        let record = LogRecordRef::parse_slow_log(block).unwrap();
+        assert_eq!(record.time.time_str, "2019-08-14T09:26:59.487776265+08:00");
+        assert_eq!(record.time.timestamp.nanosecond, 487_776_265);
+        assert_eq!((record.level, record.source), (LogLevel::Unknown, None));
+        assert_eq!(record.message.unescape(), "insert into t\nselect * from t;");
+        let fields = record.entries.iter().map(|f| format!("{}={}", f.key.unescape(), f.value.unescape())).collect::<Vec<_>>();
+        assert_eq!(fields, vec![
+            "Txn_start_ts=410450924122144769",
+            "User@Host=root[root] @ localhost [127.0.0.1]",
+            "Query_time=1.527627037",
+            "Process_time=0.07",
+            "Request_count=1",
+            "Cop_proc_addr=172.16.5.87:20171",
+            "Stats=t:pseudo",
+            "Backoff_types=[regionMiss txnLock]",
+            "Backoff_total=0.002",
+            "Is_internal=false",
+            "Succ=true",
+            "Plan=tidb_decode_plan('ZJAwCTMyXzcJMAkyMAlk')",
+        ]);
+
+        let record = LogRecordRef::parse_slow_log("# Time: 2019-08-14T01:26:59Z\n# DB: test\nuse test;").unwrap();
+        assert_eq!((record.message.unescape().as_ref(), record.entries.len()), ("use test;", 1));
+    }
+
+    #[test]
+    fn test_malformed_slow_log() {
+        fn check(block: &str, component: Component, message: &str) {
+            match LogRecordRef::parse_slow_log(block) {
+                Err(err @ ParseError::Unexpected { .. }) => {
+                    assert!(err.to_string().contains(message), "{:?}: {} should contain {:?}", block, err, message);
+                    if let ParseError::Unexpected { component: c, .. } = err {
+                        assert_eq!(c, component, "{:?}", block);
+                    }
+                }
+                other => panic!("{:?} should fail with {:?}, got {:?}", block, message, other.map(|r| r.to_owned())),
+            }
+        }
+
+        check("# Query_time: 1.5\nselect 1;", Component::Time, "`# Time:`");
+        check("select 1;", Component::Time, "`# Time:`");
+        check("# Time: 2019/08/14 09:26:59.487 +08:00\nselect 1;", Component::Time, "'-' in timestamp");
+        check("# Time: 2019-08-14T01:26:59Z\n# Query_time: 1.5\n# : 1\nselect 1;", Component::Field(1), "a key like");
+    }
+}
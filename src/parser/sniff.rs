@@ -0,0 +1,128 @@
+use tinyvec::TinyVec;
+
+use super::{artifacts::{LogFieldRef, LogRecordRef, TINY_VEC_THRESHOLD, is_record_header, scan_zap_object}, scanner::{Scanner, char_need_quote}};
+
+/// LineFormat is the format a line is decoded in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LineFormat {
+    /// `[timestamp] [level] ...`.
+    UnifiedLog,
+    /// a JSON object like `{"time":...}`.
+    Json,
+    /// the fields of zap like `{a=1,b=2}`, which don't have time.
+    ZapObject,
+    /// `key=value` pairs like `time=... level=...`.
+    Logfmt,
+    /// anything else.
+    Text,
+}
+
+impl LineFormat {
+    pub const ALL: [LineFormat; 5] = [LineFormat::UnifiedLog, LineFormat::Json, LineFormat::ZapObject, LineFormat::Logfmt, LineFormat::Text];
+
+    /// the name of the format, which is also the name of its decoder.
+    pub fn name(self) -> &'static str {
+        match self {
+            LineFormat::UnifiedLog => "uniformed-log",
+            LineFormat::Json => "json",
+            LineFormat::ZapObject => "zap-object",
+            LineFormat::Logfmt => "logfmt",
+            LineFormat::Text => "text",
+        }
+    }
+}
+
+/// Sniffed is a line decoded by the format guessed from its leading chars.
+#[derive(Debug)]
+pub enum Sniffed<'a> {
+    /// a line with a `[timestamp]` header, which is parsed with its continuation lines by the `LogReader`,
+    /// so it isn't parsed here and may still fail to parse.
+    UnifiedLog,
+    Json(LogRecordRef<'a>),
+    ZapObject(TinyVec<[LogFieldRef<'a>; TINY_VEC_THRESHOLD]>),
+    Logfmt(LogRecordRef<'a>),
+    /// anything else, including the lines which look like JSON or logfmt but fail to parse.
+    Text,
+}
+
+impl<'a> Sniffed<'a> {
+    /// decode the line by the format guessed from its leading chars.
+    pub fn sniff(line: &'a str) -> Self {
+        if is_record_header(line) {
+            Sniffed::UnifiedLog
+        } else if line.starts_with('{') {
+            LogRecordRef::parse_json(line).map(Sniffed::Json)
+                .or_else(|_| scan_zap_object(&Scanner::over(line)).map(Sniffed::ZapObject))
+                .unwrap_or(Sniffed::Text)
+        } else if looks_like_logfmt(line) {
+            LogRecordRef::parse_logfmt(line).map(Sniffed::Logfmt).unwrap_or(Sniffed::Text)
+        } else {
+            Sniffed::Text
+        }
+    }
+
+    pub fn format(&self) -> LineFormat {
+        match self {
+            Sniffed::UnifiedLog => LineFormat::UnifiedLog,
+            Sniffed::Json(_) => LineFormat::Json,
+            Sniffed::ZapObject(_) => LineFormat::ZapObject,
+            Sniffed::Logfmt(_) => LineFormat::Logfmt,
+            Sniffed::Text => LineFormat::Text,
+        }
+    }
+}
+
+/// whether the line starts a record of any format but text, the other lines after
+/// a `[timestamp]` header are its continuation lines.
+/// Lines which look like JSON or logfmt only start records if they parse, so a line like `{"a":1}` in a
+/// multi-line value is still a continuation line.
+pub fn is_record_start(line: &str) -> bool {
+    Sniffed::sniff(line).format() != LineFormat::Text
+}
+
+/// whether the line starts with a `key=` pair.
+fn looks_like_logfmt(line: &str) -> bool {
+    match line.find(|c| c == '=' || char_need_quote(c)) {
+        Some(n) => n > 0 && line[n..].starts_with('='),
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{LineFormat, Sniffed, is_record_start};
+
+    #[test]
+    fn test_sniff() {
+        let check = |line: &str, format: LineFormat| assert_eq!(Sniffed::sniff(line).format(), format, "{:?}", line);
+
+        check(r#"[2018/12/15 14:20:11.015 +08:00] [INFO] [tikv-server.rs:13] ["TiKV Started"]"#, LineFormat::UnifiedLog);
+        check("[2018/12/15 14:20:11] broken", LineFormat::UnifiedLog);
+        check(r#"{"time":"2018/12/15 14:20:11.015 +08:00","msg":"x"}"#, LineFormat::Json);
+        check("{a=1,b=2}", LineFormat::ZapObject);
+        check(r#"{"a":1}"#, LineFormat::Text);
+        check(r#"time="2018/12/15 14:20:11.015 +08:00" level=info"#, LineFormat::Logfmt);
+        check("a.b-c=\"x y\"", LineFormat::Text);
+        check("=1", LineFormat::Text);
+        check("[INFO] x", LineFormat::Text);
+        check("stack backtrace: a=1", LineFormat::Text);
+        check("  0: a=1", LineFormat::Text);
+        check("", LineFormat::Text);
+
+        match Sniffed::sniff(r#"time="2018/12/15 14:20:11.015 +08:00" level=info msg=x"#) {
+            Sniffed::Logfmt(record) => assert_eq!(record.message.unescape(), "x"),
+            other => panic!("{:?} isn't logfmt", other),
+        }
+        match Sniffed::sniff("{a=1,b=2}") {
+            Sniffed::ZapObject(fields) => assert_eq!(fields.len(), 2),
+            other => panic!("{:?} isn't a zap object", other),
+        }
+
+        assert!(is_record_start(r#"{"time":"2018/12/15 14:20:11.015 +08:00","msg":"x"}"#));
+        assert!(is_record_start("{a=1,b=2}"));
+        assert!(is_record_start(r#"time="2018/12/15 14:20:11.015 +08:00" level=info"#));
+        assert!(!is_record_start(r#"{"a":1}"#));
+        assert!(!is_record_start("a=1 b=2"));
+        assert!(!is_record_start("stack backtrace:"));
+    }
+}
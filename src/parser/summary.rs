@@ -0,0 +1,156 @@
+use std::{collections::HashMap, fmt::{self, Display}};
+
+use super::{ParseError, sniff::LineFormat};
+
+/// Failure is a group of failed lines sharing the same reason.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Failure {
+    pub count: usize,
+    /// the line number of the first failed line of this group.
+    pub first_line: usize,
+    /// the full error of the first failed line of this group.
+    pub example: String,
+}
+
+/// ErrorSummary counts the lines failed to parse by their reasons.
+#[derive(Debug, Default)]
+pub struct ErrorSummary {
+    total: usize,
+    failed: usize,
+    failures: HashMap<String, Failure>,
+}
+
+impl ErrorSummary {
+    /// count a line which is parsed successfully.
+    pub fn on_success(&mut self) {
+        self.total += 1;
+    }
+
+    /// count a line failed to parse.
+    pub fn on_failure(&mut self, line: usize, err: &ParseError) {
+        self.total += 1;
+        self.failed += 1;
+        self.failures
+            .entry(err.reason())
+            .or_insert_with(|| Failure {
+                count: 0,
+                first_line: line,
+                example: match err {
+                    ParseError::Unexpected { got, hint, .. } => format!("unexpected {} (hint: `{}`)", got, hint),
+                    ParseError::Empty => err.to_string(),
+                },
+            })
+            .count += 1;
+    }
+
+    pub fn total(&self) -> usize {
+        self.total
+    }
+
+    pub fn failed(&self) -> usize {
+        self.failed
+    }
+
+    /// the failures grouped by reason, the most frequent one comes first.
+    pub fn failures(&self) -> Vec<(&str, &Failure)> {
+        let mut failures = self.failures.iter().map(|(reason, f)| (reason.as_str(), f)).collect::<Vec<_>>();
+        failures.sort_by(|(r1, f1), (r2, f2)| f2.count.cmp(&f1.count).then(f1.first_line.cmp(&f2.first_line)).then(r1.cmp(r2)));
+        failures
+    }
+}
+
+impl Display for ErrorSummary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{} of {} records failed to parse:", self.failed, self.total)?;
+        for (reason, failure) in self.failures() {
+            writeln!(f, "{:>8} {}, e.g. line {} {}", failure.count, reason, failure.first_line, failure.example)?;
+        }
+        Ok(())
+    }
+}
+
+/// FormatSummary counts the records decoded by their formats.
+#[derive(Debug, Default)]
+pub struct FormatSummary {
+    counts: [usize; LineFormat::ALL.len()],
+}
+
+impl FormatSummary {
+    pub fn on_decoded(&mut self, format: LineFormat) {
+        self.counts[format as usize] += 1;
+    }
+
+    pub fn count(&self, format: LineFormat) -> usize {
+        self.counts[format as usize]
+    }
+
+    pub fn total(&self) -> usize {
+        self.counts.iter().sum()
+    }
+}
+
+/// like `120 records decoded: 100 uniformed-log, 15 json, 5 text`, the formats not seen are omitted.
+impl Display for FormatSummary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} records decoded:", self.total())?;
+        let seen = LineFormat::ALL.iter().filter(|format| self.count(**format) > 0);
+        for (i, format) in seen.enumerate() {
+            write!(f, "{} {} {}", if i == 0 { "" } else { "," }, self.count(*format), format.name())?;
+        }
+        writeln!(f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use crate::parser::reader::{LogReader, ReadError};
+
+    use crate::parser::sniff::LineFormat;
+
+    use super::{ErrorSummary, FormatSummary};
+
+    #[test]
+    fn test_summary() {
+        let input = "[2018/12/15 14:20:11.015 +08:00] [INFO] [tikv-server.rs:13] [\"TiKV Started\"]\n\
+            [2018/12/15 14:20:11] [INFO] [tikv-server.rs:13] [\"TiKV Started\"]\n\
+            [2018/12/15 14:20:11.015 +08:00] [INFO] [tikv-server.rs:13] [\"TiKV Started\"]\n\
+            [2018/12/15 14:20:12 +08:00] [INFO] [tikv-server.rs:13] [\"TiKV Started\"]\n\
+            [2018/12/15 14:20:11.015 +08:00] [INFO] tikv-server.rs:13 [\"TiKV Started\"]\n";
+        let mut summary = ErrorSummary::default();
+        let mut reader = LogReader::new(Cursor::new(input));
+        while let Some(record) = reader.next_record() {
+            match record {
+                Ok(_) => summary.on_success(),
+                Err(ReadError::Parse { line, err }) => summary.on_failure(line, &err),
+                Err(err) => panic!("unexpected error {}", err),
+            }
+        }
+
+        assert_eq!((summary.total(), summary.failed()), (5, 3));
+        let failures = summary.failures();
+        assert_eq!(failures.len(), 2);
+        assert_eq!(failures[0].0, "excepting '.' in timestamp");
+        assert_eq!((failures[0].1.count, failures[0].1.first_line), (2, 2));
+        assert_eq!(failures[0].1.example, "unexpected ] (hint: `20:11>]<] [INFO] [`)");
+        assert_eq!(failures[1].0, "excepting [");
+        assert_eq!((failures[1].1.count, failures[1].1.first_line), (1, 5));
+        assert_eq!(summary.to_string(), concat!(
+            "3 of 5 records failed to parse:\n",
+            "       2 excepting '.' in timestamp, e.g. line 2 unexpected ] (hint: `20:11>]<] [INFO] [`)\n",
+            "       1 excepting [, e.g. line 5 unexpected t (hint: `NFO] >t<tikv-serve`)\n",
+        ));
+    }
+
+    #[test]
+    fn test_format_summary() {
+        let mut summary = FormatSummary::default();
+        assert_eq!(summary.to_string(), "0 records decoded:\n");
+        for format in [LineFormat::Text, LineFormat::UnifiedLog, LineFormat::UnifiedLog, LineFormat::Json] {
+            summary.on_decoded(format);
+        }
+        assert_eq!((summary.total(), summary.count(LineFormat::UnifiedLog), summary.count(LineFormat::Logfmt)), (4, 2, 0));
+        assert_eq!(summary.to_string(), "4 records decoded: 2 uniformed-log, 1 json, 1 text\n");
+    }
+}
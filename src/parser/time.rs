@@ -0,0 +1,423 @@
+use std::cmp::Ordering;
+
+use super::{ParseError, scanner::Scanner, value::parse_duration};
+
+const NANOS_PER_SEC: i128 = 1_000_000_000;
+
+/// Timestamp is the parsed form of the time header of a log line,
+/// i.e. `2018/12/15 14:20:11.015 +08:00`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Timestamp {
+    pub year: i32,
+    pub month: u8,
+    pub day: u8,
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+    /// the sub-second part, the unified log format only keeps milliseconds of it.
+    pub nanosecond: u32,
+    /// the offset to UTC in minutes, `+08:00` would be `480`.
+    pub offset_minutes: i16,
+}
+
+impl Timestamp {
+    /// the unix epoch, `1970/01/01 00:00:00.000 +00:00`.
+    pub const EPOCH: Timestamp = Timestamp { year: 1970, month: 1, day: 1, hour: 0, minute: 0, second: 0, nanosecond: 0, offset_minutes: 0 };
+
+    /// the nanoseconds since the unix epoch (`1970/01/01 00:00:00 +00:00`),
+    /// which overflows `i64` for the years after 2262.
+    pub fn epoch_nanos(&self) -> i128 {
+        let days = days_from_civil(self.year, self.month, self.day);
+        let local_secs = days * 86400
+            + self.hour as i64 * 3600
+            + self.minute as i64 * 60
+            + self.second as i64;
+        let secs = local_secs - self.offset_minutes as i64 * 60;
+        secs as i128 * NANOS_PER_SEC + self.nanosecond as i128
+    }
+
+    pub fn millisecond(&self) -> u32 {
+        self.nanosecond / 1_000_000
+    }
+
+    /// scan a timestamp like `2018/12/15 14:20:11.015 +08:00`.
+    pub(super) fn scan_from(text: &Scanner) -> Result<Self, ParseError> {
+        let mut timestamp = Self::scan_date_time(text, '/', ' ')?;
+        separator(text, '.')?;
+        timestamp.nanosecond = Self::scan_fraction(text)?;
+        separator(text, ' ')?;
+        timestamp.offset_minutes = Self::scan_offset(text)?;
+        Ok(timestamp)
+    }
+
+    /// scan a RFC 3339 timestamp like `2019-08-14T09:26:59.487776265+08:00` or `2019-08-14T01:26:59Z`,
+    /// which is written by the slow query log of TiDB.
+    pub(super) fn scan_rfc3339_from(text: &Scanner) -> Result<Self, ParseError> {
+        let mut timestamp = Self::scan_date_time(text, '-', 'T')?;
+        if text.peek_char() == Some('.') {
+            separator(text, '.')?;
+            timestamp.nanosecond = Self::scan_fraction(text)?;
+        }
+        if text.peek_char() == Some('Z') {
+            separator(text, 'Z')?;
+        } else {
+            timestamp.offset_minutes = Self::scan_offset(text)?;
+        }
+        Ok(timestamp)
+    }
+
+    /// scan the date and the time till the seconds, like `2018/12/15 14:20:11`.
+    fn scan_date_time(text: &Scanner, date_separator: char, time_separator: char) -> Result<Self, ParseError> {
+        let year = text.digits(4, "timestamp year")? as i32;
+        separator(text, date_separator)?;
+        let month = checked(text, text.digits(2, "timestamp month")?, 1, 12, "timestamp month (01-12)")?;
+        separator(text, date_separator)?;
+        let day = text.digits(2, "timestamp day")?;
+        let day = checked(text, day, 1, days_in_month(year, month as u8) as u32, "day of the timestamp month")?;
+        separator(text, time_separator)?;
+        let hour = checked(text, text.digits(2, "timestamp hour")?, 0, 23, "timestamp hour (00-23)")?;
+        separator(text, ':')?;
+        let minute = checked(text, text.digits(2, "timestamp minute")?, 0, 59, "timestamp minute (00-59)")?;
+        separator(text, ':')?;
+        let second = checked(text, text.digits(2, "timestamp second")?, 0, 59, "timestamp second (00-59)")?;
+
+        Ok(Self {
+            year,
+            month: month as u8,
+            day: day as u8,
+            hour: hour as u8,
+            minute: minute as u8,
+            second: second as u8,
+            nanosecond: 0,
+            offset_minutes: 0,
+        })
+    }
+
+    fn scan_fraction(text: &Scanner) -> Result<u32, ParseError> {
+        let start = text.offset();
+        let fraction = text.consume_until(|c| !c.is_ascii_digit())?;
+        if fraction.is_empty() || fraction.len() > 9 {
+            return Err(text.unexpected("1 to 9 digits of timestamp fraction", text.since(start)));
+        }
+        let scale = 10u32.pow(9 - fraction.len() as u32);
+        Ok(fraction.parse::<u32>().unwrap() * scale)
+    }
+
+    fn scan_offset(text: &Scanner) -> Result<i16, ParseError> {
+        let sign = match text.peek_char() {
+            Some('+') => 1,
+            Some('-') => -1,
+            Some(ch) => return Err(text.unexpected("'+' or '-' of timestamp offset", ch)),
+            None => return Err(text.unexpected("'+' or '-' of timestamp offset", "EOF")),
+        };
+        text.consume(1)?;
+        let hours = checked(text, text.digits(2, "timestamp offset hour")?, 0, 23, "timestamp offset hour (00-23)")?;
+        separator(text, ':')?;
+        let minutes = checked(text, text.digits(2, "timestamp offset minute")?, 0, 59, "timestamp offset minute (00-59)")?;
+        Ok(sign * (hours * 60 + minutes) as i16)
+    }
+}
+
+impl PartialOrd for Timestamp {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Timestamp {
+    /// timestamps are ordered by the instant they present, so the same instant
+    /// with different offsets are still ordered by the offset to make `Ord` consistent with `Eq`.
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.epoch_nanos()
+            .cmp(&other.epoch_nanos())
+            .then(self.offset_minutes.cmp(&other.offset_minutes))
+    }
+}
+
+/// TimeBound is a bound of the time of records given by the user.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimeBound {
+    /// an instant in nanoseconds since the unix epoch.
+    Instant(i128),
+    /// a time without the offset, which is compared with the local time of records,
+    /// in nanoseconds since the unix epoch as if it is in UTC.
+    WallClock(i128),
+}
+
+impl TimeBound {
+    /// parse a time bound, which is one of:
+    /// - `now` or a duration relative to `now_nanos`, like `-15m` or `-1h30m`;
+    /// - a timestamp like `2018/12/15 14:20:11.015 +08:00` or `2018-12-15T14:20:11Z`, where the seconds,
+    ///   the fraction and the offset are optional. Without the offset it is the local time of records.
+    pub fn parse(s: &str, now_nanos: i64) -> Option<Self> {
+        let s = s.trim();
+        if s == "now" {
+            return Some(TimeBound::Instant(now_nanos as i128));
+        }
+        if s.starts_with(['-', '+']) {
+            let seconds = parse_duration(s.strip_prefix('+').unwrap_or(s))?;
+            return Some(TimeBound::Instant((now_nanos as i128).saturating_add((seconds * NANOS_PER_SEC as f64) as i128)));
+        }
+        let (timestamp, has_offset) = parse_bound_timestamp(s)?;
+        Some(if has_offset { TimeBound::Instant(timestamp.epoch_nanos()) } else { TimeBound::WallClock(timestamp.epoch_nanos()) })
+    }
+
+    /// compare the timestamp with the bound.
+    pub fn cmp_timestamp(&self, timestamp: &Timestamp) -> Ordering {
+        match self {
+            TimeBound::Instant(nanos) => timestamp.epoch_nanos().cmp(nanos),
+            TimeBound::WallClock(nanos) => {
+                let local = timestamp.epoch_nanos() + timestamp.offset_minutes as i128 * 60 * NANOS_PER_SEC;
+                local.cmp(nanos)
+            }
+        }
+    }
+}
+
+/// TimeRange is the range `[since, until)` of the time of records.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TimeRange {
+    pub since: Option<TimeBound>,
+    pub until: Option<TimeBound>,
+}
+
+impl TimeRange {
+    pub fn is_unbounded(&self) -> bool {
+        self.since.is_none() && self.until.is_none()
+    }
+
+    /// whether the timestamp is before `since`.
+    pub fn is_before(&self, timestamp: &Timestamp) -> bool {
+        self.since.is_some_and(|since| since.cmp_timestamp(timestamp) == Ordering::Less)
+    }
+
+    /// whether the timestamp is at or after `until`.
+    pub fn is_after(&self, timestamp: &Timestamp) -> bool {
+        self.until.is_some_and(|until| until.cmp_timestamp(timestamp) != Ordering::Less)
+    }
+
+    pub fn contains(&self, timestamp: &Timestamp) -> bool {
+        !self.is_before(timestamp) && !self.is_after(timestamp)
+    }
+}
+
+/// parse the timestamp of a time bound, returns whether it has the offset.
+fn parse_bound_timestamp(s: &str) -> Option<(Timestamp, bool)> {
+    let year = s.get(..4).filter(|y| y.bytes().all(|b| b.is_ascii_digit()))?.parse::<i32>().ok()?;
+    let date_separator = *s.as_bytes().get(4)?;
+    let mut ts = Timestamp { year, month: 1, day: 1, hour: 0, minute: 0, second: 0, nanosecond: 0, offset_minutes: 0 };
+    let mut has_offset = false;
+    let mut rest = &s[4..];
+    let expect = |rest: &mut &str, separators: &[char]| -> bool {
+        match rest.strip_prefix(separators) {
+            Some(remain) => { *rest = remain; true }
+            None => false,
+        }
+    };
+    let number = |rest: &mut &str, min: u32, max: u32| -> Option<u32> {
+        let text = rest.get(..2)?;
+        if !text.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+        *rest = &rest[2..];
+        let value = text.parse().ok()?;
+        (min..=max).contains(&value).then_some(value)
+    };
+
+    if !matches!(date_separator, b'/' | b'-') || !expect(&mut rest, &[date_separator as char]) {
+        return None;
+    }
+    ts.month = number(&mut rest, 1, 12)? as u8;
+    if !expect(&mut rest, &[date_separator as char]) {
+        return None;
+    }
+    ts.day = number(&mut rest, 1, days_in_month(year, ts.month) as u32)? as u8;
+    if expect(&mut rest, &[' ', 'T']) {
+        ts.hour = number(&mut rest, 0, 23)? as u8;
+        if !expect(&mut rest, &[':']) {
+            return None;
+        }
+        ts.minute = number(&mut rest, 0, 59)? as u8;
+        if expect(&mut rest, &[':']) {
+            ts.second = number(&mut rest, 0, 59)? as u8;
+            if expect(&mut rest, &['.']) {
+                let len = rest.find(|c: char| !c.is_ascii_digit()).unwrap_or(rest.len());
+                if len == 0 || len > 9 {
+                    return None;
+                }
+                ts.nanosecond = rest[..len].parse::<u32>().ok()? * 10u32.pow(9 - len as u32);
+                rest = &rest[len..];
+            }
+        }
+        rest = rest.trim_start();
+        if expect(&mut rest, &['Z']) {
+            has_offset = true;
+        } else if let Some(sign) = rest.chars().next().filter(|c| *c == '+' || *c == '-') {
+            rest = &rest[1..];
+            let hours = number(&mut rest, 0, 23)?;
+            expect(&mut rest, &[':']);
+            let minutes = number(&mut rest, 0, 59)?;
+            let offset = (hours * 60 + minutes) as i16;
+            ts.offset_minutes = if sign == '-' { -offset } else { offset };
+            has_offset = true;
+        }
+    }
+    rest.is_empty().then_some((ts, has_offset))
+}
+
+fn separator(text: &Scanner, expected: char) -> Result<(), ParseError> {
+    match text.peek_char() {
+        Some(ch) if ch == expected => { text.consume(1)?; Ok(()) }
+        Some(ch) => Err(text.unexpected(format!("'{}' in timestamp", expected), ch)),
+        None => Err(text.unexpected(format!("'{}' in timestamp", expected), "EOF")),
+    }
+}
+
+fn checked(text: &Scanner, value: u32, min: u32, max: u32, expected: &str) -> Result<u32, ParseError> {
+    if value < min || value > max {
+        return Err(text.unexpected(format!("valid {}", expected), value));
+    }
+    Ok(value)
+}
+
+fn is_leap_year(year: i32) -> bool {
+    (year % 4 == 0 && year % 100 != 0) || year % 400 == 0
+}
+
+fn days_in_month(year: i32, month: u8) -> u8 {
+    match month {
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// the days since the unix epoch of a date of the proleptic gregorian calendar.
+/// See http://howardhinnant.github.io/date_algorithms.html#days_from_civil .
+fn days_from_civil(year: i32, month: u8, day: u8) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year } as i64;
+    let era = if year >= 0 { year } else { year - 399 } / 400;
+    let year_of_era = year - era * 400;
+    let month = month as i64;
+    let day_of_year = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day as i64 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parser::scanner::Scanner;
+
+    use super::{TimeBound, TimeRange, Timestamp};
+
+    fn parse(s: &str) -> Result<Timestamp, String> {
+        let scanner = Scanner::over(s);
+        Timestamp::scan_from(&scanner).map_err(|e| e.to_string())
+    }
+
+    #[test]
+    fn test_parse_timestamp() {
+        let ts = parse("2018/12/15 14:20:11.015 +08:00").unwrap();
+        assert_eq!((ts.year, ts.month, ts.day), (2018, 12, 15));
+        assert_eq!((ts.hour, ts.minute, ts.second, ts.millisecond()), (14, 20, 11, 15));
+        assert_eq!(ts.offset_minutes, 480);
+        // date -d '2018-12-15T14:20:11.015+08:00' +%s%N
+        assert_eq!(ts.epoch_nanos(), 1_544_854_811_015_000_000);
+
+        let epoch = parse("1970/01/01 00:00:00.000 +00:00").unwrap();
+        assert_eq!(epoch.epoch_nanos(), 0);
+        let before_epoch = parse("1969/12/31 23:59:59.500 -00:30").unwrap();
+        assert_eq!(before_epoch.epoch_nanos(), 1_799_500_000_000);
+        let leap = parse("2020/02/29 00:00:00.000 +00:00").unwrap();
+        assert_eq!(leap.epoch_nanos(), 1_582_934_400 * 1_000_000_000);
+    }
+
+    #[test]
+    fn test_timestamp_order() {
+        let beijing = parse("2018/12/15 14:20:11.015 +08:00").unwrap();
+        let utc = parse("2018/12/15 06:20:11.015 +00:00").unwrap();
+        let later = parse("2018/12/15 06:20:11.016 +00:00").unwrap();
+        assert_eq!(beijing.epoch_nanos(), utc.epoch_nanos());
+        assert!(beijing < later);
+        assert!(utc < later);
+    }
+
+    #[test]
+    fn test_malformed_timestamp() {
+        fn check(s: &str, message: &str) {
+            let err = parse(s).expect_err(s);
+            assert!(err.contains(message), "the error of {} is {}, which doesn't contain {}", s, err, message);
+        }
+
+        check("2018/13/15 14:20:11.015 +08:00", "valid timestamp month");
+        check("2018/02/29 14:20:11.015 +08:00", "valid day of the timestamp month");
+        check("2018/12/15 24:20:11.015 +08:00", "valid timestamp hour");
+        check("2018/12/15 14:20:11 +08:00", "excepting '.' in timestamp");
+        check("2018/12/15 14:20:11.015", "excepting ' ' in timestamp");
+        check("2018/12/15 14:20:11.015 08:00", "timestamp offset");
+        check("2018-12-15 14:20:11.015 +08:00", "excepting '/' in timestamp");
+        check("18/12/15 14:20:11.015 +08:00", "digit of timestamp year");
+        check("2018/12/15 14:20:11. +08:00", "timestamp fraction");
+    }
+
+    #[test]
+    fn test_parse_rfc3339() {
+        let parse = |s: &str| Timestamp::scan_rfc3339_from(&Scanner::over(s)).map_err(|e| e.to_string());
+
+        let ts = parse("2019-08-14T09:26:59.487776265+08:00").unwrap();
+        assert_eq!((ts.year, ts.month, ts.day, ts.hour, ts.minute, ts.second), (2019, 8, 14, 9, 26, 59));
+        assert_eq!((ts.nanosecond, ts.offset_minutes), (487_776_265, 480));
+        assert_eq!(parse("2019-08-14T01:26:59.487776265Z").unwrap().epoch_nanos(), ts.epoch_nanos());
+        assert_eq!(parse("2019-08-14T01:26:59Z").unwrap().nanosecond, 0);
+        assert!(parse("2019/08/14T09:26:59+08:00").unwrap_err().contains("'-' in timestamp"));
+        assert!(parse("2019-08-14 09:26:59+08:00").unwrap_err().contains("'T' in timestamp"));
+        assert!(parse("2019-08-14T09:26:59").unwrap_err().contains("timestamp offset"));
+    }
+
+    #[test]
+    fn test_time_bound() {
+        const NOW: i64 = 1_544_854_811_015_000_000;
+        fn check(s: &str, expected: Option<TimeBound>) {
+            assert_eq!(TimeBound::parse(s, NOW), expected, "failed to parse {}", s);
+        }
+
+        check("now", Some(TimeBound::Instant(NOW as i128)));
+        check("-15m", Some(TimeBound::Instant(NOW as i128 - 900_000_000_000)));
+        check("-1h30m", Some(TimeBound::Instant(NOW as i128 - 5_400_000_000_000)));
+        check("+500ms", Some(TimeBound::Instant(NOW as i128 + 500_000_000)));
+        check("2018/12/15 14:20:11.015 +08:00", Some(TimeBound::Instant(NOW as i128)));
+        check("2018-12-15T06:20:11.015Z", Some(TimeBound::Instant(NOW as i128)));
+        check("2018-12-15T14:20:11.015+0800", Some(TimeBound::Instant(NOW as i128)));
+        check("2018/12/15 14:20:11.015", Some(TimeBound::WallClock(NOW as i128 + 8 * 3_600_000_000_000)));
+        check("2018/12/15 14:20", Some(TimeBound::WallClock(NOW as i128 + 8 * 3_600_000_000_000 - 11_015_000_000)));
+        check("2018/12/15", Some(TimeBound::WallClock(1_544_832_000_000_000_000)));
+        check("2018/12/32", None);
+        check("2018/12-15", None);
+        check("2018/12/15 14", None);
+        check("-15", None);
+        check("yesterday", None);
+    }
+
+    #[test]
+    fn test_time_range() {
+        let range = TimeRange {
+            since: TimeBound::parse("2018/12/15 14:20:00", 0),
+            until: TimeBound::parse("2018/12/15 06:21:00 +00:00", 0),
+        };
+        assert!(range.is_before(&parse("2018/12/15 14:19:59.999 +08:00").unwrap()));
+        assert!(range.contains(&parse("2018/12/15 14:20:00.000 +08:00").unwrap()));
+        // `since` has no offset, so it is compared with the local time of records.
+        assert!(range.is_before(&parse("2018/12/15 06:20:30.000 +00:00").unwrap()));
+        assert!(range.contains(&parse("2018/12/15 14:20:00.000 +09:00").unwrap()));
+        assert!(range.is_after(&parse("2018/12/15 14:21:00.000 +08:00").unwrap()));
+        assert!(!TimeRange::default().is_after(&parse("2018/12/15 14:21:00.000 +08:00").unwrap()));
+
+        // the nanoseconds since the epoch of far future or past years don't fit in `i64`.
+        let far_future = parse("3000/01/01 00:00:00.000 +08:00").unwrap();
+        assert!(range.is_after(&far_future));
+        assert!(!range.is_before(&far_future));
+        assert!(range.is_before(&parse("0001/01/01 00:00:00.000 +08:00").unwrap()));
+        assert_eq!(far_future.epoch_nanos(), 32_503_651_200 * 1_000_000_000);
+    }
+}
@@ -0,0 +1,241 @@
+/// The largest integer which can be presented by a `f64` exactly, JSON tools like `jq` lose
+/// precision on integers greater than this (i.e. TSOs), so they are left as strings.
+const MAX_SAFE_INTEGER: i64 = (1 << 53) - 1;
+
+/// TypedValue is the value of a field inferred from its text.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TypedValue {
+    Int(i64),
+    Float(f64),
+    Bool(bool),
+    /// a duration like `992.547µs` or `1m3s`, normalized to seconds.
+    Duration { seconds: f64 },
+    /// a size like `1.2GiB`, normalized to bytes.
+    Size { bytes: f64 },
+    /// a rate like `128MB/s`, normalized to bytes per second.
+    Rate { bytes_per_second: f64 },
+}
+
+impl TypedValue {
+    /// infer the type of the text of a value, `None` if it is just a string.
+    pub fn infer(s: &str) -> Option<Self> {
+        match s {
+            "true" => return Some(TypedValue::Bool(true)),
+            "false" => return Some(TypedValue::Bool(false)),
+            _ => {}
+        }
+        let first = s.bytes().next()?;
+        if !(first.is_ascii_digit() || first == b'-' || first == b'+' || first == b'.') {
+            return None;
+        }
+        // numbers with leading zeros like `007` are ids or codes, which would lose the zeros as numbers.
+        let digits = s.trim_start_matches(['-', '+']).as_bytes();
+        if digits.len() > 1 && digits[0] == b'0' && digits[1].is_ascii_digit() {
+            return None;
+        }
+        if is_integer(s) {
+            // integers out of the safe range (even out of `i64`) would lose precision as floats too.
+            return s.parse::<i64>().ok().filter(|n| (-MAX_SAFE_INTEGER..=MAX_SAFE_INTEGER).contains(n)).map(TypedValue::Int);
+        }
+        if let Some(f) = parse_float(s) {
+            return Some(TypedValue::Float(f));
+        }
+        if let Some(seconds) = parse_duration(s) {
+            return Some(TypedValue::Duration { seconds });
+        }
+        if let Some(size) = s.strip_suffix("/s") {
+            return parse_size(size).map(|bytes_per_second| TypedValue::Rate { bytes_per_second });
+        }
+        parse_size(s).map(|bytes| TypedValue::Size { bytes })
+    }
+
+    /// the normalized value as a number, `None` for booleans.
+    pub fn as_f64(&self) -> Option<f64> {
+        match *self {
+            TypedValue::Int(n) => Some(n as f64),
+            TypedValue::Bool(_) => None,
+            TypedValue::Float(f)
+            | TypedValue::Duration { seconds: f }
+            | TypedValue::Size { bytes: f }
+            | TypedValue::Rate { bytes_per_second: f } => Some(f),
+        }
+    }
+
+    /// the unit of the normalized value, if there is one.
+    pub fn unit(&self) -> Option<&'static str> {
+        match self {
+            TypedValue::Duration { .. } => Some("s"),
+            TypedValue::Size { .. } => Some("B"),
+            TypedValue::Rate { .. } => Some("B/s"),
+            _ => None,
+        }
+    }
+}
+
+/// whether `s` is an integer like `42`, `-42` or `+42`, whatever large it is.
+fn is_integer(s: &str) -> bool {
+    let digits = s.strip_prefix(['-', '+']).unwrap_or(s);
+    !digits.is_empty() && digits.bytes().all(|b| b.is_ascii_digit())
+}
+
+/// split the leading decimal number (`-1.5`, `3`, `.5`) from `s`.
+fn split_number(s: &str) -> Option<(f64, &str)> {
+    let end = s
+        .char_indices()
+        .find(|(i, c)| !(c.is_ascii_digit() || *c == '.' || (*i == 0 && (*c == '-' || *c == '+'))))
+        .map(|(i, _)| i)
+        .unwrap_or_else(|| s.len());
+    let (number, rest) = s.split_at(end);
+    if !number.bytes().any(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let value = number.parse::<f64>().ok()?;
+    Some((value, rest))
+}
+
+fn parse_float(s: &str) -> Option<f64> {
+    let mantissa_end = s.find(['e', 'E']).unwrap_or(s.len());
+    let (number, rest) = split_number(&s[..mantissa_end])?;
+    if !rest.is_empty() {
+        return None;
+    }
+    if mantissa_end == s.len() {
+        return Some(number).filter(|f| f.is_finite());
+    }
+    let exponent = &s[mantissa_end+1..];
+    let exponent_digits = exponent.strip_prefix(['-', '+']).unwrap_or(exponent);
+    if exponent_digits.is_empty() || !exponent_digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    s.parse::<f64>().ok().filter(|f| f.is_finite())
+}
+
+/// parse durations printed by Go (`1h2m3.5s`, `992.547µs`) and Rust (`1.345s`, `15ns`) in seconds.
+pub(super) fn parse_duration(s: &str) -> Option<f64> {
+    let (negative, mut rest) = match s.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, s),
+    };
+    if rest.is_empty() {
+        return None;
+    }
+    let mut seconds = 0f64;
+    while !rest.is_empty() {
+        let (value, unit_and_rest) = split_number(rest)?;
+        if value < 0.0 {
+            return None;
+        }
+        let unit_len = unit_and_rest.find(|c: char| c.is_ascii_digit() || c == '.').unwrap_or(unit_and_rest.len());
+        let (unit, remain) = unit_and_rest.split_at(unit_len);
+        seconds += match unit {
+            "ns" => value / 1e9,
+            "us" | "µs" | "μs" => value / 1e6,
+            "ms" => value / 1e3,
+            "s" => value,
+            "m" => value * 60.0,
+            "h" => value * 3600.0,
+            _ => return None,
+        };
+        rest = remain;
+    }
+    Some(if negative { -seconds } else { seconds }).filter(|f| f.is_finite())
+}
+
+/// parse sizes like `128MB` or `1.2GiB` in bytes.
+/// TiKV prints binary sizes with the `KB`, `MB`... units, so they are taken as binary units too.
+fn parse_size(s: &str) -> Option<f64> {
+    let (value, unit) = split_number(s)?;
+    let scale = match unit {
+        "B" => 1u64,
+        "K" | "KB" | "KiB" => 1 << 10,
+        "M" | "MB" | "MiB" => 1 << 20,
+        "G" | "GB" | "GiB" => 1 << 30,
+        "T" | "TB" | "TiB" => 1 << 40,
+        "P" | "PB" | "PiB" => 1 << 50,
+        _ => return None,
+    };
+    Some(value * scale as f64).filter(|f| f.is_finite())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TypedValue::{self, *};
+
+    fn check(s: &str, expected: Option<TypedValue>) {
+        let got = TypedValue::infer(s);
+        match (got, expected) {
+            (Some(Float(a)), Some(Float(b)))
+            | (Some(Duration { seconds: a }), Some(Duration { seconds: b }))
+            | (Some(Size { bytes: a }), Some(Size { bytes: b }))
+            | (Some(Rate { bytes_per_second: a }), Some(Rate { bytes_per_second: b })) => {
+                assert!((a - b).abs() <= b.abs() * 1e-12, "failed to infer {}: {:?} != {:?}", s, got, expected)
+            }
+            _ => assert_eq!(got, expected, "failed to infer {}", s),
+        }
+    }
+
+    #[test]
+    fn test_infer_numbers() {
+        check("123000102231", Some(Int(123000102231)));
+        check("-42", Some(Int(-42)));
+        check("0", Some(Int(0)));
+        check("+5", Some(Int(5)));
+        check("007", None);
+        check("-007", None);
+        check("00.5", None);
+        check("0.5", Some(Float(0.5)));
+        check("0e1", Some(Float(0.0)));
+        // TSOs lose precision as JSON numbers.
+        check("418928178437472257", None);
+        check("18446744073709551615", None);
+        check("-18446744073709551615", None);
+        check("3.25", Some(Float(3.25)));
+        check("-0.5", Some(Float(-0.5)));
+        check("1e-7", Some(Float(1e-7)));
+        check("2.5E3", Some(Float(2500.0)));
+        check("true", Some(Bool(true)));
+        check("false", Some(Bool(false)));
+        check("True", None);
+        check("NaN", None);
+        check("inf", None);
+        check("1e400", None);
+        check(&"9".repeat(400), None);
+        check(&format!("{}.5", "9".repeat(400)), None);
+        check("1.2.3", None);
+        check("192.168.0.123:12345", None);
+        check("-", None);
+        check("", None);
+    }
+
+    #[test]
+    fn test_infer_durations() {
+        check("1.345s", Some(Duration { seconds: 1.345 }));
+        check("992.547µs", Some(Duration { seconds: 992.547e-6 }));
+        check("992.547μs", Some(Duration { seconds: 992.547e-6 }));
+        check("15ns", Some(Duration { seconds: 15e-9 }));
+        check("20ms", Some(Duration { seconds: 0.02 }));
+        check("1m3s", Some(Duration { seconds: 63.0 }));
+        check("1h2m3.5s", Some(Duration { seconds: 3723.5 }));
+        check("-1.5s", Some(Duration { seconds: -1.5 }));
+        check("0s", Some(Duration { seconds: 0.0 }));
+        check("05s", None);
+        check(&format!("{}.5h", "9".repeat(400)), None);
+        check("1d", None);
+        check("1m3", None);
+        check("12:15:13", None);
+    }
+
+    #[test]
+    fn test_infer_sizes() {
+        check("128MB", Some(Size { bytes: 128.0 * 1024.0 * 1024.0 }));
+        check("1.2GiB", Some(Size { bytes: 1.2 * 1024.0 * 1024.0 * 1024.0 }));
+        check("512B", Some(Size { bytes: 512.0 }));
+        check("128MB/s", Some(Rate { bytes_per_second: 128.0 * 1024.0 * 1024.0 }));
+        check("1KiB/s", Some(Rate { bytes_per_second: 1024.0 }));
+        check("128 MB/s", None);
+        check("128XB", None);
+        check("MB", None);
+        check(&format!("{}.5PB", "9".repeat(400)), None);
+        check(&format!("{}.5PB/s", "9".repeat(400)), None);
+    }
+}
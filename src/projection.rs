@@ -0,0 +1,282 @@
+use std::{borrow::Cow, collections::{HashMap, HashSet}, fmt::{self, Display}};
+
+use crate::parser::artifacts::{LogFieldRef, LogStr};
+
+/// Key is a key of records in the output.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Key {
+    Message,
+    Level,
+    Source,
+    Time,
+    /// all fields.
+    Fields,
+    /// the field with the key.
+    Field(String),
+    Instance,
+    File,
+    Line,
+}
+
+/// the keys written when no key is selected.
+const DEFAULT_KEYS: &[Key] = &[Key::Message, Key::Level, Key::Source, Key::Time, Key::Fields, Key::Instance, Key::File, Key::Line];
+
+impl Key {
+    /// parse a key like `message`, `fields` or `fields.region_id`.
+    pub fn parse(s: &str) -> Option<Self> {
+        let key = match s {
+            "message" => Key::Message,
+            "level" => Key::Level,
+            "source" => Key::Source,
+            "time" => Key::Time,
+            "fields" => Key::Fields,
+            "_instance" => Key::Instance,
+            "_file" => Key::File,
+            "_line" => Key::Line,
+            _ => match s.strip_prefix("fields.") {
+                Some(field) if !field.is_empty() => Key::Field(field.to_owned()),
+                _ => return None,
+            },
+        };
+        Some(key)
+    }
+
+    /// the name of the key in the output if it isn't renamed.
+    pub fn name(&self) -> &str {
+        match self {
+            Key::Message => "message",
+            Key::Level => "level",
+            Key::Source => "source",
+            Key::Time => "time",
+            Key::Fields => "fields",
+            Key::Field(field) => field,
+            Key::Instance => "_instance",
+            Key::File => "_file",
+            Key::Line => "_line",
+        }
+    }
+}
+
+impl Display for Key {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Key::Field(field) => write!(f, "fields.{}", field),
+            key => f.write_str(key.name()),
+        }
+    }
+}
+
+/// DuplicateKeys is what to do with the fields with the same key in a record.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DuplicateKeys {
+    /// write all of them with the same key.
+    #[default]
+    All,
+    First,
+    Last,
+    /// collect the values into an array at where the first of them is.
+    Array,
+    /// write the second one as `<key>_2`, the third one as `<key>_3` and so on,
+    /// skipping the suffixed keys taken by other fields.
+    Suffix,
+}
+
+/// FieldValues is the values written for a key of fields.
+#[derive(Debug, PartialEq)]
+pub enum FieldValues<'a, 'r> {
+    One(&'a LogStr<'r>),
+    /// the values of a duplicate key collected by `DuplicateKeys::Array`.
+    Many(Vec<&'a LogStr<'r>>),
+}
+
+/// ProjectedField is a field to write, with its name in the output.
+#[derive(Debug, PartialEq)]
+pub struct ProjectedField<'a, 'r> {
+    pub name: Cow<'a, str>,
+    pub values: FieldValues<'a, 'r>,
+}
+
+/// Projection decides which keys of records are written, in which order, and their names.
+#[derive(Debug, Clone, Default)]
+pub struct Projection {
+    select: Option<Vec<Key>>,
+    rename: HashMap<Key, String>,
+    rename_fields: HashMap<String, String>,
+    duplicate_keys: DuplicateKeys,
+}
+
+impl Projection {
+    /// only write the keys, in the order of them.
+    pub fn select(mut self, keys: Vec<Key>) -> Self {
+        self.select = Some(keys);
+        self
+    }
+
+    pub fn rename(mut self, key: Key, name: impl Into<String>) -> Self {
+        match key {
+            Key::Field(field) => self.rename_fields.insert(field, name.into()),
+            key => self.rename.insert(key, name.into()),
+        };
+        self
+    }
+
+    pub fn duplicate_keys(mut self, duplicate_keys: DuplicateKeys) -> Self {
+        self.duplicate_keys = duplicate_keys;
+        self
+    }
+
+    /// the keys selected, `None` if the keys aren't selected.
+    pub fn selection(&self) -> Option<&[Key]> {
+        self.select.as_deref()
+    }
+
+    /// the new name of the key, `None` if it isn't renamed.
+    pub fn renamed(&self, key: &Key) -> Option<&str> {
+        match key {
+            Key::Field(field) => self.rename_fields.get(field),
+            key => self.rename.get(key),
+        }.map(String::as_str)
+    }
+
+    /// the keys to write, in order.
+    pub fn keys(&self) -> &[Key] {
+        self.select.as_deref().unwrap_or(DEFAULT_KEYS)
+    }
+
+    /// the fields selected one by one, `None` if all fields are written.
+    pub fn selected_fields(&self) -> Option<Vec<&str>> {
+        let keys = self.select.as_ref()?;
+        if keys.contains(&Key::Fields) {
+            return None;
+        }
+        Some(keys.iter().filter_map(|key| match key {
+            Key::Field(field) => Some(field.as_str()),
+            _ => None,
+        }).collect())
+    }
+
+    /// the name of the key in the output.
+    pub fn name<'a>(&'a self, key: &'a Key) -> &'a str {
+        match key {
+            Key::Field(field) => self.field_name(field),
+            key => self.rename.get(key).map(String::as_str).unwrap_or_else(|| key.name()),
+        }
+    }
+
+    /// the name of the field in the output.
+    pub fn field_name<'a>(&'a self, field: &'a str) -> &'a str {
+        self.rename_fields.get(field).map(String::as_str).unwrap_or(field)
+    }
+
+    /// the fields to write with their names, in the order of the selection if fields are selected one by one.
+    pub fn fields<'a, 'r: 'a>(&'a self, entries: &'a [LogFieldRef<'r>]) -> Vec<ProjectedField<'a, 'r>> {
+        let keyed = entries.iter().map(|entry| (entry.key.unescape(), &entry.value));
+        let keyed = match self.selected_fields() {
+            None => keyed.collect::<Vec<_>>(),
+            Some(selected) => {
+                let all = keyed.collect::<Vec<_>>();
+                selected.into_iter()
+                    .flat_map(|field| all.iter().filter(move |(key, _)| key == field).cloned())
+                    .collect()
+            }
+        };
+        // the names which suffixed names must not collide with, e.g. a real field `a_2`.
+        let mut taken = HashSet::new();
+        if self.duplicate_keys == DuplicateKeys::Suffix {
+            taken.extend(keyed.iter().map(|(key, _)| self.field_name(key).to_owned()));
+        }
+        let mut fields = Vec::with_capacity(keyed.len());
+        for (i, (key, value)) in keyed.iter().enumerate() {
+            let mut suffix = None;
+            let values = match self.duplicate_keys {
+                DuplicateKeys::All => FieldValues::One(value),
+                DuplicateKeys::First if keyed[..i].iter().any(|(other, _)| other == key) => continue,
+                DuplicateKeys::Last if keyed[i + 1..].iter().any(|(other, _)| other == key) => continue,
+                DuplicateKeys::First | DuplicateKeys::Last => FieldValues::One(value),
+                DuplicateKeys::Array if keyed[..i].iter().any(|(other, _)| other == key) => continue,
+                DuplicateKeys::Array => {
+                    let values = keyed[i..].iter().filter(|(other, _)| other == key).map(|(_, value)| *value).collect::<Vec<_>>();
+                    if values.len() == 1 { FieldValues::One(value) } else { FieldValues::Many(values) }
+                }
+                DuplicateKeys::Suffix => {
+                    let before = keyed[..i].iter().filter(|(other, _)| other == key).count();
+                    suffix = Some(before + 1).filter(|n| *n > 1);
+                    FieldValues::One(value)
+                }
+            };
+            let name = match self.rename_fields.get(key.as_ref()) {
+                Some(name) => Cow::Borrowed(name.as_str()),
+                None => key.clone(),
+            };
+            let name = match suffix {
+                Some(n) => Cow::Owned((n..).map(|n| format!("{}_{}", name, n)).find(|suffixed| taken.insert(suffixed.clone())).unwrap()),
+                None => name,
+            };
+            fields.push(ProjectedField { name, values });
+        }
+        fields
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parser::artifacts::with_log_record;
+
+    use super::{DuplicateKeys, FieldValues, Key, Projection};
+
+    #[test]
+    fn test_projection() {
+        let keys = "time,message,fields.region_id,fields.a.b,_file".split(',').map(|k| Key::parse(k).unwrap()).collect::<Vec<_>>();
+        assert_eq!(keys.iter().map(|k| k.to_string()).collect::<Vec<_>>(), vec!["time", "message", "fields.region_id", "fields.a.b", "_file"]);
+        assert_eq!(Key::parse("fields."), None);
+        assert_eq!(Key::parse("msg"), None);
+
+        let projection = Projection::default()
+            .select(keys)
+            .rename(Key::parse("fields.region_id").unwrap(), "region")
+            .rename(Key::Time, "ts");
+        assert_eq!(projection.keys().len(), 5);
+        assert_eq!(projection.selected_fields(), Some(vec!["region_id", "a.b"]));
+        assert_eq!(projection.name(&Key::Time), "ts");
+        assert_eq!(projection.name(&Key::Message), "message");
+        assert_eq!(projection.field_name("region_id"), "region");
+
+        let projection = Projection::default().select(vec![Key::Fields, Key::Field("a".to_owned())]);
+        assert_eq!(projection.selected_fields(), None);
+        assert_eq!(Projection::default().keys().len(), 8);
+    }
+
+    #[test]
+    fn test_duplicate_keys() {
+        let line = r#"[2021/03/09 12:15:13.145 +08:00] [INFO] [a.rs:1] ["x"] [a=1] [b=2] [a=3] [c=4] [a="5"]"#;
+        let fields = |projection: Projection| with_log_record(line, |r| {
+            assert_eq!(r.entries.len(), 5);
+            projection.fields(&r.entries).into_iter().map(|field| {
+                let values = match field.values {
+                    FieldValues::One(value) => value.unescape().into_owned(),
+                    FieldValues::Many(values) => format!("{:?}", values.iter().map(|v| v.unescape()).collect::<Vec<_>>()),
+                };
+                format!("{}={}", field.name, values)
+            }).collect::<Vec<_>>().join(" ")
+        }).unwrap();
+        let policy = |duplicate_keys| Projection::default().duplicate_keys(duplicate_keys);
+
+        assert_eq!(fields(policy(DuplicateKeys::All)), "a=1 b=2 a=3 c=4 a=5");
+        assert_eq!(fields(policy(DuplicateKeys::First)), "a=1 b=2 c=4");
+        assert_eq!(fields(policy(DuplicateKeys::Last)), "b=2 c=4 a=5");
+        assert_eq!(fields(policy(DuplicateKeys::Array)), r#"a=["1", "3", "5"] b=2 c=4"#);
+        assert_eq!(fields(policy(DuplicateKeys::Suffix)), "a=1 b=2 a_2=3 c=4 a_3=5");
+        assert_eq!(
+            fields(policy(DuplicateKeys::Suffix).rename(Key::Field("a".to_owned()), "z").select(vec![Key::Field("c".to_owned()), Key::Field("a".to_owned())])),
+            "c=4 z=1 z_2=3 z_3=5",
+        );
+        assert_eq!(fields(policy(DuplicateKeys::Last).select(vec![Key::Field("a".to_owned()), Key::Field("b".to_owned())])), "a=5 b=2");
+
+        let line = r#"[2021/03/09 12:15:13.145 +08:00] [INFO] [a.rs:1] ["x"] [a=1] [a=2] [a_2=3] [a=4] [a_3=5]"#;
+        let names = with_log_record(line, |r| {
+            policy(DuplicateKeys::Suffix).fields(&r.entries).into_iter().map(|field| field.name.into_owned()).collect::<Vec<_>>()
+        }).unwrap();
+        assert_eq!(names, vec!["a", "a_4", "a_2", "a_5", "a_3"]);
+    }
+}
@@ -0,0 +1,269 @@
+use std::io::{self, Write};
+use crate::{json_writer::write_quoted_str, output::{Origin, WriteRecord}, parser::{artifacts::*, char_need_quote, owned::LogRecord}, projection::{FieldValues, Key, Projection}};
+
+/// ToUnifiedLog writes things in the unified log format, so they can be parsed back to the same things.
+pub trait ToUnifiedLog {
+    fn write_log_to<W: Write>(&self, w: W) -> io::Result<()>;
+}
+
+/// write `s` as a string of the unified log format.
+/// It is quoted if it is empty or has any char `char_need_quote`, and escaped as a JSON string when quoted.
+pub fn write_log_str<W: Write>(mut w: W, s: &str) -> io::Result<()> {
+    if !s.is_empty() && !s.chars().any(char_need_quote) {
+        return w.write_all(s.as_bytes());
+    }
+    write_quoted_str(w, s, true)
+}
+
+impl ToUnifiedLog for &str {
+    fn write_log_to<W: Write>(&self, w: W) -> io::Result<()> {
+        write_log_str(w, self)
+    }
+}
+
+/// strings are written in the canonical quoting, whatever the quoting they were parsed from.
+impl <'a> ToUnifiedLog for LogStr<'a> {
+    fn write_log_to<W: Write>(&self, w: W) -> io::Result<()> {
+        match self {
+            Self::Quoted(_) => write_log_str(w, &self.unescape()),
+            Self::Unquoted(s) => write_log_str(w, s),
+        }
+    }
+}
+
+impl ToUnifiedLog for LogLevel {
+    fn write_log_to<W: Write>(&self, mut w: W) -> io::Result<()> {
+        let desc = match self {
+            LogLevel::Debug => "DEBUG",
+            LogLevel::Info => "INFO",
+            LogLevel::Warn => "WARN",
+            LogLevel::Error => "ERROR",
+            LogLevel::Fatal => "FATAL",
+            LogLevel::Unknown => "UNKNOWN",
+        };
+        w.write_all(desc.as_bytes())
+    }
+}
+
+/// the source is written as `file:line`, or `<unknown>` if it is absent.
+/// It is quoted like other strings if it has any char `char_need_quote`, i.e. a path with spaces or `]`.
+impl <'a> ToUnifiedLog for Option<FileLineRef<'a>> {
+    fn write_log_to<W: Write>(&self, mut w: W) -> io::Result<()> {
+        match self {
+            Some(source) if source.file.chars().chain(source.line.chars()).any(char_need_quote) => {
+                write_log_str(w, &format!("{}:{}", source.file, source.line))
+            }
+            Some(source) => write!(w, "{}:{}", source.file, source.line),
+            None => w.write_all(b"<unknown>"),
+        }
+    }
+}
+
+/// the time is written from its timestamp as `yyyy/MM/dd HH:mm:ss.SSS ±hh:mm`, whatever the format it was parsed from,
+/// e.g. the RFC 3339 time of the slow query log. The unknown time is written as the unix epoch, since the time is required.
+impl <'a> ToUnifiedLog for TimeRef<'a> {
+    fn write_log_to<W: Write>(&self, mut w: W) -> io::Result<()> {
+        let t = &self.timestamp;
+        let sign = if t.offset_minutes < 0 { '-' } else { '+' };
+        let offset = t.offset_minutes.unsigned_abs();
+        write!(w, "{:04}/{:02}/{:02} {:02}:{:02}:{:02}.{:03} {}{:02}:{:02}",
+            t.year, t.month, t.day, t.hour, t.minute, t.second, t.millisecond(), sign, offset / 60, offset % 60)
+    }
+}
+
+impl <'a> ToUnifiedLog for LogFieldRef<'a> {
+    fn write_log_to<W: Write>(&self, mut w: W) -> io::Result<()> {
+        self.key.write_log_to(&mut w)?;
+        w.write_all(b"=")?;
+        self.value.write_log_to(&mut w)
+    }
+}
+
+impl <'a> ToUnifiedLog for LogRecordRef<'a> {
+    fn write_log_to<W: Write>(&self, w: W) -> io::Result<()> {
+        UnifiedLogFormat::default().write_record_from(self, Origin::default(), w)
+    }
+}
+
+impl ToUnifiedLog for LogRecord {
+    fn write_log_to<W: Write>(&self, w: W) -> io::Result<()> {
+        self.borrowed().write_log_to(w)
+    }
+}
+
+/// UnifiedLogFormat is the options of how a record is written as a line of the unified log format.
+/// The time, level, source and message are always written; the projection only applies to the fields,
+/// and the `_instance`, `_file` and `_line` attributes are written as fields only if they are selected.
+#[derive(Debug, Clone, Default)]
+pub struct UnifiedLogFormat {
+    projection: Projection,
+}
+
+impl UnifiedLogFormat {
+    /// only write the selected fields, with their new keys.
+    pub fn projection(mut self, projection: Projection) -> Self {
+        self.projection = projection;
+        self
+    }
+
+    pub fn write_record_from<W: Write>(&self, record: &LogRecordRef<'_>, origin: Origin<'_>, mut w: W) -> io::Result<()> {
+        w.write_all(b"[")?;
+        record.time.write_log_to(&mut w)?;
+        w.write_all(b"] [")?;
+        record.level.write_log_to(&mut w)?;
+        w.write_all(b"] [")?;
+        record.source.write_log_to(&mut w)?;
+        w.write_all(b"] [")?;
+        record.message.write_log_to(&mut w)?;
+        w.write_all(b"]")?;
+
+        for field in self.projection.fields(&record.entries) {
+            let values = match field.values {
+                FieldValues::One(value) => vec![value],
+                FieldValues::Many(values) => values,
+            };
+            for value in values {
+                w.write_all(b" [")?;
+                write_log_str(&mut w, &field.name)?;
+                w.write_all(b"=")?;
+                value.write_log_to(&mut w)?;
+                w.write_all(b"]")?;
+            }
+        }
+        for key in self.projection.selection().unwrap_or_default() {
+            let value = match key {
+                Key::Instance => origin.instance.map(str::to_owned),
+                Key::File => origin.file.map(str::to_owned),
+                Key::Line => origin.line.map(|line| line.to_string()),
+                _ => None,
+            };
+            if let Some(value) = value {
+                w.write_all(b" [")?;
+                write_log_str(&mut w, self.projection.name(key))?;
+                w.write_all(b"=")?;
+                write_log_str(&mut w, &value)?;
+                w.write_all(b"]")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl WriteRecord for UnifiedLogFormat {
+    fn write_record(&mut self, record: &LogRecordRef<'_>, origin: Origin<'_>, w: &mut dyn Write) -> io::Result<()> {
+        self.write_record_from(record, origin, &mut *w)?;
+        writeln!(w)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use crate::{output::Origin, parser::{artifacts::{LogLevel, LogRecordRef, TimeRef, with_log_record}, owned::{FileLine, LogField, LogRecord, LogString, Time}}, projection::{DuplicateKeys, Key, Projection}};
+
+    use super::{ToUnifiedLog, UnifiedLogFormat, write_log_str};
+
+    fn encode(r: &impl ToUnifiedLog) -> String {
+        let mut buf = Vec::new();
+        r.write_log_to(&mut buf).unwrap();
+        String::from_utf8(buf).unwrap()
+    }
+
+    /// the record with all strings unescaped, so records are compared regardless of their quoting.
+    fn unescaped(r: &LogRecordRef<'_>) -> LogRecord {
+        let text = |s: &crate::parser::artifacts::LogStr<'_>| LogString::Unquoted(s.unescape().into_owned());
+        LogRecord {
+            level: r.level,
+            time: r.time.to_owned(),
+            message: text(&r.message),
+            source: r.source.as_ref().map(|s| s.to_owned()),
+            entries: r.entries.iter().map(|f| LogField { key: text(&f.key), value: text(&f.value) }).collect(),
+        }
+    }
+
+    /// parse the line, which must not have any field skipped.
+    fn parse(line: &str) -> LogRecord {
+        let record = LogRecordRef::parse_with(line, |field: crate::parser::diagnostics::SkippedField<'_>| {
+            panic!("field {} of {:?} is skipped: {}", field.text, line, field.error)
+        });
+        unescaped(&record.unwrap_or_else(|err| panic!("failed to parse {:?}: {}", line, err)))
+    }
+
+    #[test]
+    fn test_log_str() {
+        fn check(from: &str, to: &str) {
+            let mut buf = Vec::new();
+            write_log_str(&mut buf, from).unwrap();
+            assert_eq!(String::from_utf8(buf).unwrap(), to, "failed to encode {:?}", from);
+        }
+
+        check("", r#""""#);
+        check("1.345s", "1.345s");
+        check(r"C:\tikv", r"C:\tikv");
+        check("TiKV Started", r#""TiKV Started""#);
+        check("[a]", r#""[a]""#);
+        check(r#"ID="abc""#, r#""ID=\"abc\"""#);
+        check("a\\b c", r#""a\\b c""#);
+        check("\u{1b}[31m\n\u{7f}µ", r#""\u001b[31m\n\u007fµ""#);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let line = r#"[2018/12/15 14:20:11.015 +08:00] [WARN] [session.go:1234] ["Slow query"] [sql="SELECT * FROM TABLE\nWHERE ID=\"abc\""] [duration=1.345s] [client=192.168.0.123:12345] ["key with space"="\x1b[31mboom"] [empty=""]"#;
+        let encoded = with_log_record(line, |r| encode(&r)).unwrap();
+        assert_eq!(encoded, r#"[2018/12/15 14:20:11.015 +08:00] [WARN] [session.go:1234] ["Slow query"] [sql="SELECT * FROM TABLE\nWHERE ID=\"abc\""] [duration=1.345s] [client=192.168.0.123:12345] ["key with space"="\u001b[31mboom"] [empty=""]"#);
+        assert_eq!(parse(&encoded), parse(line));
+
+        let line = r#"[2021/03/09 12:15:13.145 +08:00] [INFO] ["/data/my logs/[tikv]/a.rs:12"] [x]"#;
+        assert_eq!(with_log_record(line, |r| encode(&r)).unwrap(), line);
+        assert_eq!(parse(line).source.unwrap().file, "/data/my logs/[tikv]/a.rs");
+        let line = r#"[2021/03/09 12:15:13.145 +08:00] [INFO] ["C:\\logs\\\"a\".rs:12"] [x]"#;
+        assert_eq!(with_log_record(line, |r| encode(&r)).unwrap(), line);
+        assert_eq!(parse(line).source.unwrap().file, r#"C:\logs\"a".rs"#);
+
+        let block = "# Time: 2019-08-14T09:26:59.487776265-02:30\n# Query_time: 1.5\nselect 1;";
+        let encoded = encode(&LogRecordRef::parse_slow_log(block).unwrap());
+        assert_eq!(encoded, "[2019/08/14 09:26:59.487 -02:30] [UNKNOWN] [<unknown>] [\"select 1;\"] [Query_time=1.5]");
+        assert_eq!(parse(&encoded).time.timestamp.millisecond(), 487);
+
+        let line = "[2021/03/09 12:15:13.145 +08:00] [UNKNOWN] [<unknown>] [x] [a=1] [b=2] [a=3]";
+        assert_eq!(with_log_record(line, |r| encode(&r)).unwrap(), line);
+        let format = UnifiedLogFormat::default().projection(Projection::default()
+            .select(vec![Key::Field("a".to_owned()), Key::Instance, Key::Line])
+            .rename(Key::Field("a".to_owned()), "alpha")
+            .duplicate_keys(DuplicateKeys::Array));
+        let encoded = with_log_record(line, |r| {
+            let mut buf = Vec::new();
+            let origin = Origin { instance: Some("tikv-1"), file: Some("tikv.log"), line: Some(7) };
+            format.write_record_from(&r, origin, &mut buf).unwrap();
+            String::from_utf8(buf).unwrap()
+        }).unwrap();
+        assert_eq!(encoded, "[2021/03/09 12:15:13.145 +08:00] [UNKNOWN] [<unknown>] [x] [alpha=1] [alpha=3] [_instance=tikv-1] [_line=7]");
+    }
+
+    fn record() -> impl Strategy<Value = LogRecord> {
+        let time = (1970..2100i32, 1..=12u32, 1..=28u32, 0..24u32, 0..60u32, 0..60u32, 0..1000u32, -12..=14i32, prop::sample::select(vec![0, 30, 45]))
+            .prop_map(|(y, mo, d, h, mi, s, ms, oh, om)| {
+                let time_str = format!("{:04}/{:02}/{:02} {:02}:{:02}:{:02}.{:03} {}{:02}:{:02}", y, mo, d, h, mi, s, ms, if oh < 0 { '-' } else { '+' }, oh.abs(), om);
+                let timestamp = TimeRef::parse(&format!("[{}]", time_str)).unwrap().timestamp;
+                Time { time_str, timestamp }
+            });
+        let level = prop::sample::select(vec![LogLevel::Debug, LogLevel::Info, LogLevel::Warn, LogLevel::Error, LogLevel::Fatal, LogLevel::Unknown]);
+        let source = prop::option::of((any::<String>(), "[0-9]{1,5}").prop_map(|(file, line)| FileLine { file, line }));
+        let text = || any::<String>().prop_map(LogString::Unquoted);
+        let entries = prop::collection::vec((text(), text()).prop_map(|(key, value)| LogField { key, value }), 0..8);
+        (time, level, source, text(), entries)
+            .prop_map(|(time, level, source, message, entries)| LogRecord { level, time, message, source, entries })
+    }
+
+    proptest! {
+        #[test]
+        fn test_parse_encoded(record in record()) {
+            let encoded = encode(&record);
+            prop_assert_eq!(parse(&encoded), record);
+            // the encoding is canonical, so encoding the parsed record again gives the same line.
+            prop_assert_eq!(with_log_record(&encoded, |r| encode(&r)).unwrap(), encoded);
+        }
+    }
+}